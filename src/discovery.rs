@@ -0,0 +1,297 @@
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::error::ConfigError;
+use crate::git;
+use crate::nais;
+
+/// Directory in the repository root where NAIS manifests are kept by convention
+pub const NAIS_DIR: &str = ".nais";
+
+/// File names used for the main manifest or template in a directory
+const TEMPLATE_NAMES: [&str; 2] = ["nais.yaml", "nais.yml"];
+
+/// A config file, with an optional variables file, found in the `.nais/` directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// Path to the manifest or template
+    pub config: PathBuf,
+    /// Path to the variables file, if the config is a template
+    pub variables: Option<PathBuf>,
+    /// Environment name, taken from the variables file or manifest file name
+    pub env: Option<String>,
+    /// Name of the subfolder, for repositories with one folder per application
+    pub app: Option<String>,
+}
+
+impl Candidate {
+    /// Name used to select the candidate with `--env`, e.g. `dev` or `my-app/dev`
+    pub fn name(&self) -> Option<String> {
+        match (&self.app, &self.env) {
+            (Some(app), Some(env)) => Some(format!("{}/{}", app, env)),
+            (None, Some(env)) => Some(env.clone()),
+            (Some(app), None) => Some(app.clone()),
+            (None, None) => None,
+        }
+    }
+
+    /// Checks whether `--env` selects this candidate
+    fn matches(&self, env: &str) -> bool {
+        self.name().as_deref() == Some(env) || self.env.as_deref() == Some(env)
+    }
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name().unwrap_or_else(|| String::from("-")))?;
+        write!(f, ": {}", self.config.display())?;
+        if let Some(variables) = &self.variables {
+            write!(f, " with {}", variables.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds config and variables files in `.nais/` in the root of the git repository
+///
+/// Both `.nais/` and its direct subfolders are searched. In each folder, YAML files with an
+/// Application or Naisjob are manifests, other Kubernetes resources such as alerts and topics
+/// are ignored, and other YAML files are variables files. A `nais.yaml` next to variables
+/// files is a template, paired with each of them. Other manifests, such as
+/// `.nais/dev-gcp.yaml`, are used as they are.
+///
+/// The environment name is the file name without extension and without a `vars-` prefix or
+/// `-vars` suffix, so both `vars-dev.yaml` and `dev.yaml` give `dev`.
+///
+/// # Returns
+///
+/// All candidates, sorted by name
+pub fn find_candidates() -> Vec<Candidate> {
+    let Some(repo_root) = git::get_repo_root() else {
+        return Vec::new();
+    };
+    let nais_dir = repo_root.join(NAIS_DIR);
+
+    let mut candidates = candidates_in(&nais_dir, None);
+
+    let mut subfolders: Vec<PathBuf> = std::fs::read_dir(&nais_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect()
+        })
+        .unwrap_or_default();
+    subfolders.sort();
+
+    for folder in subfolders {
+        let app = folder
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string());
+        candidates.extend(candidates_in(&folder, app));
+    }
+
+    candidates.sort_by_key(|candidate| candidate.name());
+    candidates
+}
+
+/// Picks the candidate to use
+///
+/// # Arguments
+///
+/// * `candidates` - Candidates from `find_candidates`
+/// * `env` - Environment given with `--env`, if any
+///
+/// # Errors
+///
+/// Returns `ConfigError::Discovery` listing the candidates if none or several match.
+pub fn select(candidates: Vec<Candidate>, env: Option<&str>) -> Result<Candidate, ConfigError> {
+    let discovery_error = |message: String| ConfigError::Discovery {
+        path: String::from(NAIS_DIR),
+        message,
+    };
+
+    if candidates.is_empty() {
+        return Err(discovery_error(String::from(
+            "No nais.yaml found in the .nais directory of the git repository. Use --config to give the path to nais.yaml",
+        )));
+    }
+
+    let list = candidates
+        .iter()
+        .map(|candidate| format!("\n  - {}", candidate))
+        .collect::<String>();
+
+    let mut matching: Vec<Candidate> = match env {
+        Some(env) => candidates
+            .iter()
+            .filter(|candidate| candidate.matches(env))
+            .cloned()
+            .collect(),
+        None => candidates.clone(),
+    };
+
+    match (matching.len(), env) {
+        (1, _) => Ok(matching.remove(0)),
+        (0, Some(env)) => Err(discovery_error(format!(
+            "No config found for --env {}. Found:{}",
+            env, list
+        ))),
+        (_, Some(env)) => Err(discovery_error(format!(
+            "Several configs match --env {}, use <app>/<env> to pick one. Found:{}",
+            env, list
+        ))),
+        _ => Err(discovery_error(format!(
+            "Found several configs, use --env to pick one:{}",
+            list
+        ))),
+    }
+}
+
+/// Finds the candidates in a single folder, see `find_candidates`
+fn candidates_in(dir: &Path, app: Option<String>) -> Vec<Candidate> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|extension| extension == "yaml" || extension == "yml")
+        })
+        .collect();
+    files.sort();
+
+    let file_name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    };
+
+    let (templates, others): (Vec<PathBuf>, Vec<PathBuf>) = files
+        .into_iter()
+        .partition(|path| TEMPLATE_NAMES.contains(&file_name(path).as_str()));
+    let mut manifests = Vec::new();
+    let mut variables = Vec::new();
+    for path in others {
+        match file_kind(&path) {
+            FileKind::Workload => manifests.push(path),
+            FileKind::Variables => variables.push(path),
+            FileKind::OtherResource => {}
+        }
+    }
+
+    let mut candidates = Vec::new();
+
+    for template in &templates {
+        if variables.is_empty() {
+            candidates.push(Candidate {
+                config: template.clone(),
+                variables: None,
+                env: None,
+                app: app.clone(),
+            });
+        }
+        for variables in &variables {
+            candidates.push(Candidate {
+                config: template.clone(),
+                variables: Some(variables.clone()),
+                env: Some(env_name(variables)),
+                app: app.clone(),
+            });
+        }
+    }
+
+    for manifest in manifests {
+        candidates.push(Candidate {
+            env: Some(env_name(&manifest)),
+            config: manifest,
+            variables: None,
+            app: app.clone(),
+        });
+    }
+
+    candidates
+}
+
+/// What a YAML file next to the template contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    /// A manifest with an Application or Naisjob
+    Workload,
+    /// Other Kubernetes resources, e.g. alerts or topics, which are ignored
+    OtherResource,
+    /// A variables file for the template
+    Variables,
+}
+
+/// Checks whether a file is a NAIS workload manifest, another Kubernetes resource or a
+/// variables file
+///
+/// Only documents with an `apiVersion` in `nais.io/` and kind Application or Naisjob count as
+/// workloads, the same check as when the config is parsed. Templates are often not valid YAML
+/// before processing, so when parsing fails the top-level keys are looked for line by line
+/// instead.
+fn file_kind(path: &Path) -> FileKind {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return FileKind::Variables;
+    };
+
+    let documents: Result<Vec<serde_yaml::Value>, _> = serde_yaml::Deserializer::from_str(&content)
+        .map(serde_yaml::Value::deserialize)
+        .collect();
+
+    // The apiVersion and kind of every document
+    let resources: Vec<(String, String)> = match documents {
+        Ok(documents) => documents
+            .iter()
+            .filter_map(|document| {
+                let field = |key: &str| document.get(key)?.as_str().map(str::to_string);
+                Some((field("apiVersion")?, field("kind")?))
+            })
+            .collect(),
+        Err(_) => {
+            let values = |key: &str| -> Vec<String> {
+                content
+                    .lines()
+                    .filter_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+                    .map(|value| value.trim().trim_matches(['"', '\'']).to_string())
+                    .collect()
+            };
+            values("apiVersion")
+                .into_iter()
+                .zip(values("kind"))
+                .collect()
+        }
+    };
+
+    if resources
+        .iter()
+        .any(|(api_version, kind)| nais::is_workload(api_version, kind))
+    {
+        FileKind::Workload
+    } else if resources.is_empty() {
+        FileKind::Variables
+    } else {
+        FileKind::OtherResource
+    }
+}
+
+/// Environment name from a file name, e.g. `dev` for `vars-dev.yaml`
+fn env_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    stem.strip_prefix("vars-")
+        .or_else(|| stem.strip_suffix("-vars"))
+        .unwrap_or(&stem)
+        .to_string()
+}
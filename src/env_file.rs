@@ -1,48 +1,43 @@
 use std::collections::BTreeMap;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, Write};
 use std::path::Path;
 
+use crate::dotenv;
+use crate::error::{ConfigError, Error};
 use crate::git;
 
 /// Reads and parses an environment file into a BTreeMap
 ///
+/// The file is parsed with the dotenv grammar described in `dotenv::parse`, so `export`
+/// prefixes, quoted and multi-line values, escapes and inline comments are supported.
+///
 /// # Arguments
 ///
 /// * `file_path` - Path to the environment file
 ///
 /// # Returns
 ///
-/// * `Result<BTreeMap<String, String>, io::Error>` - BTreeMap of key-value pairs or an error
-pub fn parse_env_file<P: AsRef<Path>>(file_path: P) -> Result<BTreeMap<String, String>, io::Error> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let mut env_vars = BTreeMap::new();
-
-    for line in reader.lines() {
-        let line = line?;
-
-        // Skip empty lines and comments
-        let trimmed = line.trim();
-        if trimmed.is_empty() || trimmed.starts_with('#') {
-            continue;
-        }
-
-        // Parse key=value pairs
-        if let Some(idx) = trimmed.find('=') {
-            let key = trimmed[..idx].trim();
-            let value = trimmed[(idx + 1)..].trim();
-
-            // Remove quotes if they exist
-            let value = value.trim_matches(|c| c == '\'' || c == '"');
+/// * `Result<BTreeMap<String, String>, Error>` - BTreeMap of key-value pairs, where later
+///   lines override earlier ones, or an error
+///
+/// # Errors
+///
+/// Returns `Error::Io` if the file cannot be read, and `ConfigError::EnvFile` with the line
+/// and column if it cannot be parsed.
+pub fn parse_env_file<P: AsRef<Path>>(file_path: P) -> Result<BTreeMap<String, String>, Error> {
+    let path = file_path.as_ref();
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| Error::io(format!("Failed to read {}", path.display()), e))?;
 
-            if !key.is_empty() {
-                env_vars.insert(key.to_string(), value.to_string());
-            }
-        }
-    }
+    let env_vars = dotenv::parse(&content).map_err(|e| {
+        Error::Config(ConfigError::EnvFile {
+            path: path.display().to_string(),
+            location: e.location,
+            message: e.message,
+        })
+    })?;
 
-    Ok(env_vars)
+    Ok(env_vars.into_iter().collect())
 }
 
 /// Reads and parses multiple environment files into a single BTreeMap
@@ -53,10 +48,10 @@ pub fn parse_env_file<P: AsRef<Path>>(file_path: P) -> Result<BTreeMap<String, S
 ///
 /// # Returns
 ///
-/// * `Result<BTreeMap<String, String>, io::Error>` - Combined BTreeMap of key-value pairs or an error
+/// * `Result<BTreeMap<String, String>, Error>` - Combined BTreeMap of key-value pairs or an error
 pub fn parse_multiple_env_files<P: AsRef<Path>>(
     file_paths: Vec<P>,
-) -> Result<BTreeMap<String, String>, io::Error> {
+) -> Result<BTreeMap<String, String>, Error> {
     let mut combined_env_vars = BTreeMap::new();
 
     for path in file_paths {
@@ -75,6 +70,7 @@ pub fn parse_multiple_env_files<P: AsRef<Path>>(
 ///
 /// * `filename` - Path to the file where environment variables will be saved
 /// * `env_vars` - BTreeMap containing environment variables as key-value pairs
+/// * `format` - Format to write, see `Format`
 ///
 /// # Returns
 ///
@@ -83,12 +79,13 @@ pub fn parse_multiple_env_files<P: AsRef<Path>>(
 /// # Details
 ///
 /// This function:
-/// 1. Creates a file and writes environment variables in the format `KEY=VALUE`
+/// 1. Creates a file and writes environment variables in the given format
 /// 2. If in a Git repository, adds the file to `.git/info/exclude` to prevent accidental commits
 /// 3. Groups added files under a "# Added by nais-env" comment in the exclude file
 pub fn save_env_vars_to_file(
     filename: &str,
     env_vars: &std::collections::BTreeMap<String, String>,
+    format: Format,
 ) -> std::io::Result<()> {
     // Convert relative path to absolute path
     let path = std::path::Path::new(filename);
@@ -96,33 +93,258 @@ pub fn save_env_vars_to_file(
     // Create file for writing
     let mut file = std::fs::File::create(path)?;
 
-    // Write each environment variable as KEY=VALUE
-    for (key, value) in env_vars {
-        use std::io::Write;
-        writeln!(file, "{}={}", key, value)?;
-    }
+    format.writer().write(&mut file, env_vars)?;
 
     // If in a git repository make sure git ignores file by adding it to .git/info/exclude
-    if git::is_in_git_repo() {
-        if let Some(exclude_path) = git::get_git_exclude_path() {
-            if exclude_path.exists() {
-                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
-                let exclude_content = std::fs::read_to_string(&exclude_path).unwrap_or_default();
-                let lines: Vec<&str> = exclude_content.lines().collect();
-
-                if !lines.contains(&file_name.as_ref()) {
-                    if let Err(e) = git::add_to_git_exclude(path) {
-                        eprintln!("Warning: Failed to add file to git exclude: {}", e);
-                    }
-                }
+    if git::is_in_git_repo()
+        && let Some(exclude_path) = git::get_git_exclude_path()
+        && exclude_path.exists()
+    {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let exclude_content = std::fs::read_to_string(&exclude_path).unwrap_or_default();
+        let lines: Vec<&str> = exclude_content.lines().collect();
+
+        if !lines.contains(&file_name.as_ref())
+            && let Err(e) = git::add_to_git_exclude(path)
+        {
+            eprintln!("Warning: Failed to add file to git exclude: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Quotes a value so it is read back unchanged, both by dotenv libraries and by `source` in sh
+///
+/// * Values with only letters, digits and `_./:@%+,=-` are written as they are
+/// * Other values are put in single quotes, which neither dotenv nor sh look inside. Newlines
+///   are kept, since both read multi-line quoted values
+/// * Values with a single quote or a carriage return are put in double quotes, with `\`, `"`,
+///   `$` and `` ` `` escaped. A carriage return is written as `\r`, which dotenv reads back but
+///   sh does not
+///
+/// # Example
+///
+/// ```text
+/// PLAIN=jdbc:postgresql://localhost:5432/app
+/// SPACES='two words # not a comment'
+/// QUOTE="it's \$HOME"
+/// ```
+pub fn quote_env_value(value: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "_./:@%+,=-".contains(c);
+
+    if value.chars().all(is_plain) {
+        return value.to_string();
+    }
+    if !value.contains(['\'', '\r']) {
+        return format!("'{}'", value);
+    }
+
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\\' | '"' | '$' | '`' => {
+                quoted.push('\\');
+                quoted.push(c);
             }
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
         }
     }
+    quoted.push('"');
+    quoted
+}
+
+/// Formats environment variables can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// `KEY=value`, quoted when needed, for dotenv libraries and `source`
+    Dotenv,
+    /// A JSON object
+    Json,
+    /// A YAML mapping
+    Yaml,
+    /// `export KEY='value'` for sh, bash and zsh
+    Sh,
+    /// `set -gx KEY 'value'` for fish
+    Fish,
+    /// `$env:KEY = 'value'` for PowerShell
+    #[value(name = "powershell")]
+    PowerShell,
+    /// `KEY=value` without quoting, for `docker run --env-file`
+    Docker,
+}
 
+impl Format {
+    /// Infers the format from the extension of a file
+    ///
+    /// # Returns
+    ///
+    /// The format, or `None` if the extension is not known. Docker env files have no
+    /// extension of their own, so `docker` must always be given explicitly.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "env" => Some(Format::Dotenv),
+            "json" => Some(Format::Json),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "sh" | "bash" | "zsh" => Some(Format::Sh),
+            "fish" => Some(Format::Fish),
+            "ps1" => Some(Format::PowerShell),
+            _ => None,
+        }
+    }
+
+    /// Returns the writer for the format
+    pub fn writer(self) -> Box<dyn EnvWriter> {
+        match self {
+            Format::Dotenv => Box::new(DotenvWriter),
+            Format::Json => Box::new(JsonWriter),
+            Format::Yaml => Box::new(YamlWriter),
+            Format::Sh => Box::new(ShWriter),
+            Format::Fish => Box::new(FishWriter),
+            Format::PowerShell => Box::new(PowerShellWriter),
+            Format::Docker => Box::new(DockerWriter),
+        }
+    }
+}
+
+/// Writes environment variables in a specific format
+pub trait EnvWriter {
+    /// Writes all environment variables
+    ///
+    /// Variables that can not be represented in the format are skipped with a warning.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails.
+    fn write(&self, out: &mut dyn Write, env_vars: &BTreeMap<String, String>) -> io::Result<()>;
+}
+
+/// Checks whether a key can be used as a variable name in a shell
+fn is_shell_identifier(key: &str) -> bool {
+    !key.starts_with(|c: char| c.is_ascii_digit())
+        && !key.is_empty()
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Writes shell assignments, skipping keys that are not valid variable names
+fn write_shell_assignments(
+    out: &mut dyn Write,
+    env_vars: &BTreeMap<String, String>,
+    shell: &str,
+    assignment: impl Fn(&str, &str) -> String,
+) -> io::Result<()> {
+    for (key, value) in env_vars {
+        if is_shell_identifier(key) {
+            writeln!(out, "{}", assignment(key, value))?;
+        } else {
+            eprintln!(
+                "Warning: Skipping {}, it is not a valid variable name in {}",
+                key, shell
+            );
+        }
+    }
     Ok(())
 }
 
-/// Deletes all files listed under the "# Added by nais-env" comment in .git/info/exclude
+/// Writes `KEY=value`, see `quote_env_value`
+pub struct DotenvWriter;
+
+impl EnvWriter for DotenvWriter {
+    fn write(&self, out: &mut dyn Write, env_vars: &BTreeMap<String, String>) -> io::Result<()> {
+        for (key, value) in env_vars {
+            writeln!(out, "{}={}", key, quote_env_value(value))?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a JSON object
+pub struct JsonWriter;
+
+impl EnvWriter for JsonWriter {
+    fn write(&self, out: &mut dyn Write, env_vars: &BTreeMap<String, String>) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *out, env_vars)?;
+        writeln!(out)
+    }
+}
+
+/// Writes a YAML mapping
+pub struct YamlWriter;
+
+impl EnvWriter for YamlWriter {
+    fn write(&self, out: &mut dyn Write, env_vars: &BTreeMap<String, String>) -> io::Result<()> {
+        let yaml = serde_yaml::to_string(env_vars)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write!(out, "{}", yaml)
+    }
+}
+
+/// Writes `export KEY='value'`, with `'` written as `'\''`
+pub struct ShWriter;
+
+impl EnvWriter for ShWriter {
+    fn write(&self, out: &mut dyn Write, env_vars: &BTreeMap<String, String>) -> io::Result<()> {
+        write_shell_assignments(out, env_vars, "sh", |key, value| {
+            format!("export {}='{}'", key, value.replace('\'', "'\\''"))
+        })
+    }
+}
+
+/// Writes `set -gx KEY 'value'`, with `\` and `'` escaped
+pub struct FishWriter;
+
+impl EnvWriter for FishWriter {
+    fn write(&self, out: &mut dyn Write, env_vars: &BTreeMap<String, String>) -> io::Result<()> {
+        write_shell_assignments(out, env_vars, "fish", |key, value| {
+            format!(
+                "set -gx {} '{}'",
+                key,
+                value.replace('\\', "\\\\").replace('\'', "\\'")
+            )
+        })
+    }
+}
+
+/// Writes `$env:KEY = 'value'`, with `'` written as `''`
+pub struct PowerShellWriter;
+
+impl EnvWriter for PowerShellWriter {
+    fn write(&self, out: &mut dyn Write, env_vars: &BTreeMap<String, String>) -> io::Result<()> {
+        for (key, value) in env_vars {
+            let value = value.replace('\'', "''");
+            if is_shell_identifier(key) {
+                writeln!(out, "$env:{} = '{}'", key, value)?;
+            } else {
+                writeln!(out, "${{env:{}}} = '{}'", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes `KEY=value` as it is, since docker does not unquote env files
+///
+/// Docker reads one variable per line, so values with newlines are skipped.
+pub struct DockerWriter;
+
+impl EnvWriter for DockerWriter {
+    fn write(&self, out: &mut dyn Write, env_vars: &BTreeMap<String, String>) -> io::Result<()> {
+        for (key, value) in env_vars {
+            if value.contains(['\n', '\r']) {
+                eprintln!(
+                    "Warning: Skipping {}, docker env files can not contain newlines",
+                    key
+                );
+            } else {
+                writeln!(out, "{}={}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Deletes all files and directories listed under the "# Added by nais-env" comment in .git/info/exclude
 ///
 /// # Returns
 ///
@@ -140,7 +362,9 @@ pub fn clear_env_files() -> io::Result<()> {
     };
 
     // Get repository root directory
-    let repo_root = git::get_repo_root();
+    let Some(repo_root) = git::get_repo_root() else {
+        return Ok(());
+    };
 
     // Read the exclude file
     let exclude_content = std::fs::read_to_string(&exclude_path)?;
@@ -159,7 +383,7 @@ pub fn clear_env_files() -> io::Result<()> {
         } else if in_nais_env_section {
             if line.is_empty() {
                 in_nais_env_section = false;
-                updated_content.push_str("\n");
+                updated_content.push('\n');
             } else {
                 files_to_delete.push(line.to_string());
                 continue; // Skip this line in the updated content
@@ -172,9 +396,27 @@ pub fn clear_env_files() -> io::Result<()> {
 
     // Delete the identified files
     for file in &files_to_delete {
-        // Files are relative to repository root now
-        let file_path = std::path::Path::new(&repo_root).join(file);
-        if file_path.exists() {
+        // Files are relative to repository root, and never outside of it
+        let relative = std::path::Path::new(file);
+        if relative.is_absolute()
+            || relative
+                .components()
+                .any(|c| matches!(c, std::path::Component::ParentDir))
+        {
+            eprintln!("Warning: Skipping {}, it is outside the repository", file);
+            continue;
+        }
+
+        let file_path = repo_root.join(relative);
+        if git::has_tracked_files(&file_path) {
+            eprintln!(
+                "Warning: Skipping {}, it contains files tracked by git",
+                file
+            );
+        } else if file_path.is_dir() {
+            std::fs::remove_dir_all(&file_path)?;
+            println!("Deleted directory: {}", file_path.display());
+        } else if file_path.exists() {
             std::fs::remove_file(&file_path)?;
             println!("Deleted env file: {}", file_path.display());
         }
@@ -191,3 +433,159 @@ pub fn clear_env_files() -> io::Result<()> {
 
     Ok(())
 }
+
+/// A difference between two sets of environment variables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvChange {
+    /// The variable is only in the new set
+    Added { key: String, value: String },
+    /// The variable is only in the old set
+    Removed { key: String, value: String },
+    /// The variable has a different value in the new set
+    Changed {
+        key: String,
+        old: String,
+        new: String,
+    },
+}
+
+/// Compares two sets of environment variables
+///
+/// # Arguments
+///
+/// * `old` - Environment variables before, e.g. from an existing env file
+/// * `new` - Environment variables after, e.g. fetched from Kubernetes
+///
+/// # Returns
+///
+/// * `Vec<EnvChange>` - The changes, sorted by key
+pub fn diff_env_vars(
+    old: &BTreeMap<String, String>,
+    new: &BTreeMap<String, String>,
+) -> Vec<EnvChange> {
+    let keys: std::collections::BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+
+    keys.into_iter()
+        .filter_map(|key| match (old.get(key), new.get(key)) {
+            (None, Some(value)) => Some(EnvChange::Added {
+                key: key.clone(),
+                value: value.clone(),
+            }),
+            (Some(value), None) => Some(EnvChange::Removed {
+                key: key.clone(),
+                value: value.clone(),
+            }),
+            (Some(old), Some(new)) if old != new => Some(EnvChange::Changed {
+                key: key.clone(),
+                old: old.clone(),
+                new: new.clone(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Characters the generated values are made of, weighted towards the ones that need quoting
+    const ALPHABET: &[char] = &[
+        'a', 'B', '7', '_', '-', '.', '/', ':', '=', ' ', ' ', '\t', '\n', '\n', '#', '#', '\'',
+        '\'', '"', '"', '\\', '\\', '$', '$', '`', '{', '}', '(', ')', '!', '*', '?', '~', '&',
+        ';', '|', '<', '>', 'æ', 'ø', '€', '🔑',
+    ];
+
+    /// A small xorshift generator, so failures can be reproduced from the seed
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn value(&mut self, carriage_returns: bool) -> String {
+            let len = self.below(24);
+            (0..len)
+                .map(|_| {
+                    if carriage_returns && self.below(20) == 0 {
+                        '\r'
+                    } else {
+                        ALPHABET[self.below(ALPHABET.len())]
+                    }
+                })
+                .collect()
+        }
+    }
+
+    /// Writes `values` the way `nais-env fetch` writes `.env`
+    fn write_dotenv(values: &BTreeMap<String, String>) -> String {
+        let mut out = Vec::new();
+        DotenvWriter.write(&mut out, values).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn written_values_round_trip_through_the_parser() {
+        for seed in 1..=200u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let values: BTreeMap<String, String> = (0..12)
+                .map(|i| (format!("KEY_{}", i), rng.value(true)))
+                .collect();
+
+            let content = write_dotenv(&values);
+            let parsed: BTreeMap<String, String> = dotenv::parse(&content)
+                .unwrap_or_else(|e| panic!("seed {}: {:?}\n{}", seed, e, content))
+                .into_iter()
+                .collect();
+
+            assert_eq!(parsed, values, "seed {}:\n{}", seed, content);
+        }
+    }
+
+    #[test]
+    fn written_values_round_trip_through_sh() {
+        for seed in 1..=25u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x2545_F491_4F6C_DD1D));
+            let values: BTreeMap<String, String> = (0..12)
+                .map(|i| (format!("KEY_{}", i), rng.value(false)))
+                .collect();
+
+            let content = write_dotenv(&values);
+            let output = std::process::Command::new("sh")
+                .args(["-c", "set -a; eval \"$0\"; env -0", &content])
+                .env_clear()
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+
+            let sourced: BTreeMap<String, String> = String::from_utf8_lossy(&output.stdout)
+                .split('\0')
+                .filter_map(|entry| entry.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+
+            for (key, value) in &values {
+                assert_eq!(
+                    sourced.get(key),
+                    Some(value),
+                    "seed {}, {}:\n{}",
+                    seed,
+                    key,
+                    content
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,586 @@
+use std::fmt;
+
+/// A position in a file, both one-based
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Errors that can occur anywhere in nais-env
+///
+/// Every variant maps to a stable exit code, see `Error::exit_code`.
+#[derive(Debug)]
+pub enum Error {
+    /// The NAIS configuration could not be loaded
+    Config(ConfigError),
+    /// The credentials could not be obtained, or the cluster rejected them
+    Auth { message: String, hint: String },
+    /// The Kubernetes context does not exist in the kubeconfig
+    MissingContext { message: String, hint: String },
+    /// The cluster could not be reached
+    Connection { message: String, hint: String },
+    /// A resource was not found in the cluster
+    NotFound(String),
+    /// Any other error from the Kubernetes API or configuration
+    Kubernetes(String),
+    /// The policy does not allow the context, its server, or a secret
+    Policy(String),
+    /// Some secrets, config maps or keys could not be fetched
+    ///
+    /// Each failure is printed when it is found, so this only holds how many there were.
+    PartialFetch(usize),
+    /// A local file could not be read or written
+    Io {
+        context: String,
+        source: std::io::Error,
+    },
+}
+
+impl Error {
+    /// Creates an error from a Kubernetes API error
+    ///
+    /// Failures that the user can fix themselves get a hint with the fix:
+    ///
+    /// * a failing credential plugin, or status 401, becomes `Error::Auth` with a hint to
+    ///   re-authenticate
+    /// * status 403 becomes `Error::Auth` with a hint to check access to the namespace
+    /// * a refused or timed out connection becomes `Error::Connection` with a hint to connect
+    ///   naisdevice
+    ///
+    /// Status 404 becomes `Error::NotFound`, and everything else `Error::Kubernetes`.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - Description of what was being done, e.g. "Failed to get secret 'x'"
+    /// * `error` - The error from the Kubernetes client
+    pub fn from_kube(context: String, error: kube::Error) -> Self {
+        match error {
+            kube::Error::Api(response) if response.code == 401 => Error::Auth {
+                message: format!("{}: {}", context, response.message),
+                hint: String::from(
+                    "Your credentials are expired or invalid. Log in again with `nais login` \
+                     (or `gcloud auth login --update-adc`) and retry",
+                ),
+            },
+            kube::Error::Api(response) if response.code == 403 => Error::Auth {
+                message: format!("{}: {}", context, response.message),
+                hint: String::from(
+                    "You are logged in, but not allowed to read this. Check that you are a \
+                     member of the team that owns the namespace",
+                ),
+            },
+            kube::Error::Api(response) if response.code == 404 => {
+                Error::NotFound(format!("{}: {}", context, response.message))
+            }
+            error => {
+                if let Some(hint) = find_in_chain(&error, auth_plugin_hint) {
+                    Error::Auth {
+                        message: format!("{}: {}", context, error),
+                        hint,
+                    }
+                } else if find_in_chain(&error, connection_failed).is_some() {
+                    Error::Connection {
+                        message: format!("{}: {}", context, error),
+                        hint: String::from(
+                            "Could not reach the cluster. Make sure naisdevice is connected \
+                             and retry",
+                        ),
+                    }
+                } else {
+                    Error::Kubernetes(format!("{}: {}", context, error))
+                }
+            }
+        }
+    }
+
+    /// Creates an error from a failure to load the kubeconfig
+    ///
+    /// A missing context or kubeconfig becomes `Error::MissingContext`, with the contexts
+    /// that are available.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The Kubernetes context that was requested
+    /// * `error` - The error from loading the kubeconfig
+    pub fn from_kubeconfig(context: &str, error: kube::config::KubeconfigError) -> Self {
+        use kube::config::KubeconfigError;
+
+        match error {
+            KubeconfigError::LoadContext(_)
+            | KubeconfigError::LoadClusterOfContext(_)
+            | KubeconfigError::FindPath
+            | KubeconfigError::ReadConfig(..) => {
+                let available = kube::config::Kubeconfig::read()
+                    .map(|kubeconfig| {
+                        kubeconfig
+                            .contexts
+                            .into_iter()
+                            .map(|named| named.name)
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+
+                let hint = if available.is_empty() {
+                    String::from(
+                        "No contexts found. Set up your kubeconfig with `nais kubeconfig` and retry",
+                    )
+                } else {
+                    format!(
+                        "Set up your kubeconfig with `nais kubeconfig`, or use --context with one of: {}",
+                        available.join(", ")
+                    )
+                };
+
+                Error::MissingContext {
+                    message: format!(
+                        "Kubernetes context '{}' is not available: {}",
+                        context, error
+                    ),
+                    hint,
+                }
+            }
+            error => Error::Kubernetes(format!(
+                "Failed to load kubernetes config for context '{}': {}",
+                context, error
+            )),
+        }
+    }
+
+    /// Creates an error from a failed file operation
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - Description of what was being done, e.g. "Failed to write .env"
+    /// * `source` - The error from the file operation
+    pub fn io(context: impl Into<String>, source: std::io::Error) -> Self {
+        Error::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// Returns the process exit code for this error
+    ///
+    /// The codes are stable, so scripts can rely on them:
+    ///
+    /// * `1` - a local file could not be read or written
+    /// * `10`-`18` - the configuration or an env file could not be loaded, see
+    ///   `ConfigError::exit_code`
+    /// * `20` - the credentials could not be obtained, or the cluster rejected them
+    /// * `21` - the workload or another resource was not found
+    /// * `22` - any other Kubernetes error
+    /// * `23` - some secrets or config maps could not be fetched, the environment is incomplete
+    /// * `24` - the Kubernetes context does not exist
+    /// * `25` - the cluster could not be reached
+    /// * `26` - the policy does not allow the context or its server
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Io { .. } => 1,
+            Error::Config(e) => e.exit_code(),
+            Error::Auth { .. } => 20,
+            Error::NotFound(_) => 21,
+            Error::Kubernetes(_) => 22,
+            Error::PartialFetch(_) => 23,
+            Error::MissingContext { .. } => 24,
+            Error::Connection { .. } => 25,
+            Error::Policy(_) => 26,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Config(e) => write!(f, "{}", e),
+            Error::Auth { message, hint }
+            | Error::MissingContext { message, hint }
+            | Error::Connection { message, hint } => {
+                write!(f, "{}\n  hint: {}", message, hint)
+            }
+            Error::NotFound(message) | Error::Kubernetes(message) | Error::Policy(message) => {
+                write!(f, "{}", message)
+            }
+            Error::PartialFetch(1) => {
+                write!(
+                    f,
+                    "The environment is incomplete, 1 source could not be fetched"
+                )
+            }
+            Error::PartialFetch(count) => write!(
+                f,
+                "The environment is incomplete, {} sources could not be fetched",
+                count
+            ),
+            Error::Io { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Config(e) => Some(e),
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(error: ConfigError) -> Self {
+        Error::Config(error)
+    }
+}
+
+/// Walks the source chain of an error, returning the first match of `find`
+fn find_in_chain<T>(
+    error: &(dyn std::error::Error + 'static),
+    find: impl Fn(&(dyn std::error::Error + 'static)) -> Option<T>,
+) -> Option<T> {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if let Some(found) = find(error) {
+            return Some(found);
+        }
+        current = error.source();
+    }
+    None
+}
+
+/// Returns a hint for errors from running the credential plugin in the kubeconfig
+fn auth_plugin_hint(error: &(dyn std::error::Error + 'static)) -> Option<String> {
+    use kube::client::AuthError;
+
+    match error.downcast_ref::<AuthError>()? {
+        AuthError::AuthExecStart(_) => Some(String::from(
+            "The credential plugin in your kubeconfig could not be started. Install it \
+             (e.g. `gcloud components install gke-gcloud-auth-plugin`) and retry",
+        )),
+        AuthError::AuthExecRun { .. } => Some(String::from(
+            "The credential plugin in your kubeconfig failed, your login has probably \
+             expired. Log in again with `nais login` (or `gcloud auth login --update-adc`) \
+             and retry",
+        )),
+        _ => Some(String::from(
+            "Could not get credentials for the cluster. Log in again with `nais login` \
+             (or `gcloud auth login --update-adc`) and retry",
+        )),
+    }
+}
+
+/// Returns `Some` for network errors where the cluster could not be reached
+fn connection_failed(error: &(dyn std::error::Error + 'static)) -> Option<()> {
+    use std::io::ErrorKind;
+
+    let error = error.downcast_ref::<std::io::Error>()?;
+    matches!(
+        error.kind(),
+        ErrorKind::ConnectionRefused
+            | ErrorKind::ConnectionReset
+            | ErrorKind::TimedOut
+            | ErrorKind::HostUnreachable
+            | ErrorKind::NetworkUnreachable
+    )
+    .then_some(())
+}
+
+/// A line in the original template, shown for errors in templated configurations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateLine {
+    pub line: usize,
+    pub text: String,
+}
+
+/// Errors that can occur while loading a NAIS configuration file
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The configuration file could not be read
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    /// The variables file could not be read or is not valid YAML
+    Variables { path: String, message: String },
+    /// The template could not be rendered with the variables
+    Template {
+        path: String,
+        location: Option<Location>,
+        message: String,
+    },
+    /// The configuration is not valid YAML
+    Syntax {
+        path: String,
+        location: Option<Location>,
+        message: String,
+        template_line: Option<Box<TemplateLine>>,
+    },
+    /// The configuration is valid YAML, but does not match the expected structure
+    Schema {
+        path: String,
+        location: Option<Location>,
+        field: Option<String>,
+        message: String,
+        template_line: Option<Box<TemplateLine>>,
+    },
+    /// The configuration has no Application or Naisjob, or several without a selector
+    Workload { path: String, message: String },
+    /// The profile does not exist in the project configuration
+    Profile { path: String, message: String },
+    /// No config, or several configs, were found in the `.nais/` directory
+    Discovery { path: String, message: String },
+    /// An env file, such as an override file, is not valid dotenv
+    EnvFile {
+        path: String,
+        location: Location,
+        message: String,
+    },
+}
+
+impl ConfigError {
+    /// Creates an error from a `serde_yaml` error, splitting out the location and field path
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the configuration file
+    /// * `error` - The error from parsing the configuration
+    /// * `content` - The content that was parsed, after template processing
+    /// * `template` - The original template, if the configuration was processed with variables
+    pub fn from_yaml(
+        path: &str,
+        error: &serde_yaml::Error,
+        content: &str,
+        template: Option<&str>,
+    ) -> Self {
+        let location = error.location().map(|location| Location {
+            line: location.line(),
+            column: location.column(),
+        });
+
+        // serde_yaml formats errors as "<field path>: <message> at line <n> column <n>"
+        let mut message = error.to_string();
+        if let Some(index) = message.rfind(" at line ") {
+            message.truncate(index);
+        }
+        let (field, message) = match message.split_once(": ") {
+            Some((field, rest)) if !field.is_empty() && !field.contains(' ') => {
+                (Some(field.to_string()), rest.to_string())
+            }
+            _ => (None, message),
+        };
+
+        let template_line = match (template, location) {
+            (Some(template), Some(location)) => {
+                find_template_line(template, content, location.line)
+            }
+            _ => None,
+        };
+
+        // Errors about the content of a value have a field path, syntax errors do not
+        if field.is_some() || message.starts_with("missing field") {
+            ConfigError::Schema {
+                path: path.to_string(),
+                location,
+                field,
+                message,
+                template_line,
+            }
+        } else {
+            ConfigError::Syntax {
+                path: path.to_string(),
+                location,
+                message,
+                template_line,
+            }
+        }
+    }
+
+    /// Creates an error from a failed template rendering
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to the configuration file
+    /// * `error` - The error from Handlebars
+    pub fn from_template(path: &str, error: &handlebars::RenderError) -> Self {
+        let location = match (error.line_no, error.column_no) {
+            (Some(line), Some(column)) => Some(Location { line, column }),
+            _ => None,
+        };
+
+        // Syntax errors wrap a TemplateError, whose own message includes a source excerpt
+        let message = match error.reason() {
+            handlebars::RenderErrorReason::TemplateError(e) => e.reason().to_string(),
+            reason => reason.to_string(),
+        };
+
+        ConfigError::Template {
+            path: path.to_string(),
+            location,
+            message,
+        }
+    }
+
+    /// Returns the process exit code for this kind of error
+    ///
+    /// Each category has its own code, so scripts can react to them:
+    ///
+    /// * `10` - the configuration file could not be read
+    /// * `11` - the configuration is not valid YAML
+    /// * `12` - the configuration does not match the expected structure
+    /// * `13` - no workload, or several workloads without a selector
+    /// * `14` - the variables file could not be read or parsed
+    /// * `15` - the template could not be rendered
+    /// * `16` - the profile does not exist in the project configuration
+    /// * `17` - no config, or several configs, were found in `.nais/`
+    /// * `18` - an env file, such as an override file, is not valid dotenv
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ConfigError::Read { .. } => 10,
+            ConfigError::Syntax { .. } => 11,
+            ConfigError::Schema { .. } => 12,
+            ConfigError::Workload { .. } => 13,
+            ConfigError::Variables { .. } => 14,
+            ConfigError::Template { .. } => 15,
+            ConfigError::Profile { .. } => 16,
+            ConfigError::Discovery { .. } => 17,
+            ConfigError::EnvFile { .. } => 18,
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let write_location =
+            |f: &mut fmt::Formatter<'_>, path: &str, location: &Option<Location>| match location {
+                Some(location) => write!(f, "{}:{}:{}", path, location.line, location.column),
+                None => write!(f, "{}", path),
+            };
+
+        match self {
+            ConfigError::Read { path, source } => {
+                write!(f, "Failed to read config file {}: {}", path, source)
+            }
+            ConfigError::Variables { path, message } => {
+                write!(f, "Failed to parse variables file {}: {}", path, message)
+            }
+            ConfigError::Template {
+                path,
+                location,
+                message,
+            } => {
+                write!(f, "Failed to render template ")?;
+                write_location(f, path, location)?;
+                write!(f, ": {}", message)
+            }
+            ConfigError::Syntax {
+                path,
+                location,
+                message,
+                template_line,
+            } => {
+                write!(f, "Invalid YAML in ")?;
+                write_location(f, path, location)?;
+                write!(f, ": {}", message)?;
+                write_template_line(f, template_line)
+            }
+            ConfigError::Schema {
+                path,
+                location,
+                field,
+                message,
+                template_line,
+            } => {
+                write!(f, "Invalid configuration in ")?;
+                write_location(f, path, location)?;
+                write!(f, ": {}", message)?;
+                if let Some(field) = field {
+                    write!(f, "\n  field: {}", field)?;
+                }
+                write_template_line(f, template_line)
+            }
+            ConfigError::EnvFile {
+                path,
+                location,
+                message,
+            } => {
+                write!(f, "Invalid env file ")?;
+                write_location(f, path, &Some(*location))?;
+                write!(f, ": {}", message)
+            }
+            ConfigError::Workload { path, message }
+            | ConfigError::Profile { path, message }
+            | ConfigError::Discovery { path, message } => {
+                write!(f, "{}: {}", path, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the matching template line of an error, if there is one
+fn write_template_line(
+    f: &mut fmt::Formatter<'_>,
+    template_line: &Option<Box<TemplateLine>>,
+) -> fmt::Result {
+    match template_line {
+        Some(template_line) => write!(
+            f,
+            "\n  template line {}: {}",
+            template_line.line,
+            template_line.text.trim()
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Finds the line in the original template that produced a line in the processed content
+///
+/// Template processing can add or remove lines, for example in loops, so line numbers do
+/// not match. The line is found by looking for a template line that is identical to the
+/// processed line, or that has the same text before its first `{{`. When several lines
+/// match, the one closest to the original line number is used.
+///
+/// # Returns
+///
+/// The template line, if a match was found
+fn find_template_line(template: &str, content: &str, line: usize) -> Option<Box<TemplateLine>> {
+    let processed = content.lines().nth(line.checked_sub(1)?)?;
+    let processed_trimmed = processed.trim();
+    if processed_trimmed.is_empty() {
+        return None;
+    }
+
+    let distance = |index: usize| (index + 1).abs_diff(line);
+
+    let exact = template
+        .lines()
+        .enumerate()
+        .filter(|(_, text)| text.trim() == processed_trimmed)
+        .min_by_key(|(index, _)| distance(*index));
+
+    let candidate = exact.or_else(|| {
+        template
+            .lines()
+            .enumerate()
+            .filter(|(_, text)| {
+                let prefix = text.split("{{").next().unwrap_or_default().trim();
+                text.contains("{{") && !prefix.is_empty() && processed_trimmed.starts_with(prefix)
+            })
+            .min_by_key(|(index, _)| distance(*index))
+    });
+
+    candidate.map(|(index, text)| {
+        Box::new(TemplateLine {
+            line: index + 1,
+            text: text.to_string(),
+        })
+    })
+}
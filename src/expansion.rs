@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+/// Expands Kubernetes-style `$(VAR)` references in a string
+///
+/// This follows the semantics Kubernetes uses for `env[].value`:
+///
+/// * `$(VAR)` is replaced by the value returned from `lookup`, or left as-is if it returns `None`
+/// * `$$` is an escaped `$`, so `$$(VAR)` produces the literal text `$(VAR)`
+/// * A `$` that does not start a reference, or an unterminated `$(`, is kept literally
+///
+/// # Arguments
+///
+/// * `input` - The value to expand
+/// * `lookup` - Function returning the value of a referenced variable, if it is defined
+///
+/// # Returns
+///
+/// * `String` - The expanded value
+///
+/// # Example
+///
+/// ```
+/// let lookup = |name: &str| (name == "DB_HOST").then(|| "localhost".to_string());
+/// assert_eq!(expand("jdbc:postgresql://$(DB_HOST)/app", lookup), "jdbc:postgresql://localhost/app");
+/// assert_eq!(expand("$$(DB_HOST) costs $5", lookup), "$(DB_HOST) costs $5");
+/// ```
+pub fn expand<F>(input: &str, lookup: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(index) = rest.find('$') {
+        result.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+
+        if let Some(after_escape) = after.strip_prefix('$') {
+            result.push('$');
+            rest = after_escape;
+        } else if let Some(reference) = after.strip_prefix('(')
+            && let Some(end) = reference.find(')')
+        {
+            let name = &reference[..end];
+            match lookup(name) {
+                Some(value) => result.push_str(&value),
+                None => {
+                    result.push_str("$(");
+                    result.push_str(name);
+                    result.push(')');
+                }
+            }
+            rest = &reference[end + 1..];
+        } else {
+            result.push('$');
+            rest = after;
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Expands `$(VAR)` references in the env entries from nais.yaml and merges them into the other variables
+///
+/// Like in Kubernetes, a reference only sees variables that are defined before the entry:
+/// everything in `base` (envFrom sources, injected variables and key references) and the
+/// env entries that are declared earlier. References to other variables are left unexpanded.
+/// Values in `base` are never expanded.
+///
+/// # Arguments
+///
+/// * `base` - Variables that are available to all env entries
+/// * `env` - The env entries from nais.yaml, in the order they are declared
+///
+/// # Returns
+///
+/// * `BTreeMap<String, String>` - All variables, with the env entries expanded
+pub fn expand_env_vars(
+    base: BTreeMap<String, String>,
+    env: Vec<(String, String)>,
+) -> BTreeMap<String, String> {
+    let mut env_vars = base;
+
+    for (name, value) in env {
+        let expanded = expand(&value, |reference| env_vars.get(reference).cloned());
+        env_vars.insert(name, expanded);
+    }
+
+    env_vars
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOST" => Some(String::from("localhost")),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn references_are_replaced() {
+        assert_eq!(
+            expand("http://$(HOST):8080", lookup),
+            "http://localhost:8080"
+        );
+        assert_eq!(expand("$(HOST)$(HOST)", lookup), "localhostlocalhost");
+        assert_eq!(expand("a$(EMPTY)b", lookup), "ab");
+    }
+
+    #[test]
+    fn double_dollar_is_an_escaped_dollar() {
+        assert_eq!(expand("$$", lookup), "$");
+        assert_eq!(expand("a$$b", lookup), "a$b");
+        assert_eq!(expand("$$$$", lookup), "$$");
+        assert_eq!(expand("$$$(HOST)", lookup), "$localhost");
+    }
+
+    #[test]
+    fn escaped_reference_is_kept_literally() {
+        assert_eq!(expand("$$(HOST)", lookup), "$(HOST)");
+        assert_eq!(expand("$$(UNDEFINED)", lookup), "$(UNDEFINED)");
+    }
+
+    #[test]
+    fn unterminated_reference_is_kept_literally() {
+        assert_eq!(expand("$(HOST", lookup), "$(HOST");
+        assert_eq!(expand("$(", lookup), "$(");
+        assert_eq!(expand("$(HOST) $(HOST", lookup), "localhost $(HOST");
+    }
+
+    #[test]
+    fn lone_dollar_is_kept_literally() {
+        assert_eq!(expand("costs $5", lookup), "costs $5");
+        assert_eq!(expand("$HOST", lookup), "$HOST");
+        assert_eq!(expand("ends with $", lookup), "ends with $");
+    }
+
+    #[test]
+    fn undefined_reference_is_kept_literally() {
+        assert_eq!(expand("$(UNDEFINED)", lookup), "$(UNDEFINED)");
+        assert_eq!(expand("$()", lookup), "$()");
+        assert_eq!(
+            expand("$(UNDEFINED)/$(HOST)", lookup),
+            "$(UNDEFINED)/localhost"
+        );
+    }
+
+    #[test]
+    fn env_entries_see_base_and_earlier_entries() {
+        let base = BTreeMap::from([(String::from("HOST"), String::from("localhost"))]);
+        let env = vec![
+            (String::from("PORT"), String::from("8080")),
+            (String::from("URL"), String::from("http://$(HOST):$(PORT)")),
+        ];
+
+        let env_vars = expand_env_vars(base, env);
+        assert_eq!(env_vars["URL"], "http://localhost:8080");
+    }
+
+    #[test]
+    fn forward_reference_stays_literal() {
+        let env = vec![
+            (String::from("URL"), String::from("http://$(HOST)")),
+            (String::from("HOST"), String::from("localhost")),
+        ];
+
+        let env_vars = expand_env_vars(BTreeMap::new(), env);
+        assert_eq!(env_vars["URL"], "http://$(HOST)");
+        assert_eq!(env_vars["HOST"], "localhost");
+    }
+
+    #[test]
+    fn base_values_are_not_expanded() {
+        let base = BTreeMap::from([
+            (String::from("HOST"), String::from("localhost")),
+            (String::from("RAW"), String::from("$(HOST)")),
+        ]);
+
+        let env_vars = expand_env_vars(base, Vec::new());
+        assert_eq!(env_vars["RAW"], "$(HOST)");
+    }
+}
@@ -0,0 +1,408 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::Error;
+use crate::git;
+use crate::kubernetes_client::KubernetesClient;
+use crate::nais::FilesFrom;
+
+/// Default directory where files from `filesFrom` are written
+pub const DEFAULT_FILES_ROOT: &str = ".nais-env/files";
+
+/// Maps an in-cluster mount path to its location below the local files root
+///
+/// # Arguments
+///
+/// * `root` - Local directory where mounted files are written
+/// * `mount_path` - The `mountPath` from the NAIS configuration
+///
+/// # Returns
+///
+/// * `Option<PathBuf>` - The local directory corresponding to the mount path, or None if the
+///   mount path contains `..` and could point outside the root
+pub fn local_mount_path(root: &Path, mount_path: &str) -> Option<PathBuf> {
+    let relative = Path::new(mount_path.trim_start_matches('/'));
+    let inside_root = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+    inside_root.then(|| root.join(relative))
+}
+
+/// Writes each entry as a file in the given directory
+///
+/// Files are only readable by the current user (0600), since they usually contain secrets.
+///
+/// # Arguments
+///
+/// * `dir` - Directory to write the files to, created if it does not exist
+/// * `files` - Map from file name to file content
+///
+/// # Returns
+///
+/// * `io::Result<()>` - Success or error
+pub fn write_files(dir: &Path, files: &BTreeMap<String, Vec<u8>>) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    for (name, content) in files {
+        let path = dir.join(name);
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(&path)?;
+        io::Write::write_all(&mut file, content)?;
+
+        // The mode above only applies to new files, so make sure existing files are restricted too
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Files from a single `filesFrom` entry, fetched but not yet written to disk
+#[derive(Debug)]
+pub struct FetchedFiles {
+    /// The in-cluster `mountPath`
+    pub mount_path: String,
+    /// Where the files came from, e.g. `secret my-secret`
+    pub description: String,
+    /// Name of the secret the files were read from, if they came from a secret
+    pub secret: Option<String>,
+    /// Map from file name to file content
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+/// Fetches every secret and config map referenced by `filesFrom`
+///
+/// `emptyDir` entries give no files, while `persistentVolumeClaim` entries are skipped since
+/// their content is not available through the Kubernetes API. Nothing is written, so the
+/// caller can check for failures first, see `write_files_from`.
+///
+/// # Arguments
+///
+/// * `client` - Kubernetes client used to fetch secrets and config maps
+/// * `files_from` - The `filesFrom` entries from the NAIS configuration and the live deployment
+///
+/// # Returns
+///
+/// * `(Vec<FetchedFiles>, Vec<String>)` - The fetched entries, and a description of every
+///   secret or config map that could not be fetched
+pub async fn fetch_files_from(
+    client: &KubernetesClient,
+    files_from: &[&FilesFrom],
+) -> (Vec<FetchedFiles>, Vec<String>) {
+    let mut fetched = Vec::new();
+    let mut failures = Vec::new();
+
+    for entry in files_from {
+        let (description, result) = if let Some(secret) = &entry.secret {
+            (
+                format!("secret {}", secret),
+                client.get_secret_files(secret).await,
+            )
+        } else if let Some(configmap) = &entry.configmap {
+            (
+                format!("configmap {}", configmap),
+                client.get_config_map_files(configmap).await,
+            )
+        } else if entry.emptyDir.is_some() {
+            (String::from("emptyDir"), Ok(BTreeMap::new()))
+        } else {
+            eprintln!(
+                "Skipping {}: persistent volumes can not be fetched locally",
+                entry.mountPath
+            );
+            continue;
+        };
+
+        match result {
+            Ok(files) => fetched.push(FetchedFiles {
+                mount_path: entry.mountPath.clone(),
+                description,
+                secret: entry.secret.clone(),
+                files,
+            }),
+            Err(Error::Policy(message)) => eprintln!("Skipping {}", message),
+            Err(e) => failures.push(format!("{}: {}", description, e)),
+        }
+    }
+
+    (fetched, failures)
+}
+
+/// Writes fetched `filesFrom` entries to disk
+///
+/// Each key is written as a file in `<root>/<mountPath>`, and `emptyDir` entries are created
+/// as empty directories. If nais-env creates the root directory inside the git repository, it
+/// is added to `.git/info/exclude` so it is removed by `nais-env clear`.
+///
+/// # Arguments
+///
+/// * `fetched` - Entries from `fetch_files_from`
+/// * `root` - Local directory where the files are written
+///
+/// # Returns
+///
+/// * `Result<BTreeMap<String, PathBuf>, Error>` - Map from in-cluster mount path to the
+///   absolute local directory for every entry that was written
+///
+/// # Errors
+///
+/// Returns an error if the files cannot be written.
+pub fn write_files_from(
+    fetched: &[FetchedFiles],
+    root: &Path,
+) -> Result<BTreeMap<String, PathBuf>, Error> {
+    let mut mounts = BTreeMap::new();
+    let created_root = !root.exists();
+
+    for entry in fetched {
+        let Some(local_dir) = local_mount_path(root, &entry.mount_path) else {
+            eprintln!(
+                "Warning: Skipping {} from {}, the mount path points outside {}",
+                entry.mount_path,
+                entry.description,
+                root.display()
+            );
+            continue;
+        };
+        let write_error = |e| Error::io(format!("Failed to write {}", local_dir.display()), e);
+
+        write_files(&local_dir, &entry.files).map_err(write_error)?;
+        println!(
+            "Wrote {} file(s) from {} to {}",
+            entry.files.len(),
+            entry.description,
+            local_dir.display()
+        );
+        let absolute = std::path::absolute(&local_dir).map_err(write_error)?;
+        mounts.insert(entry.mount_path.clone(), absolute);
+    }
+
+    // Only a directory created by nais-env is removed by `nais-env clear`
+    if !mounts.is_empty() && !git::is_in_git_exclude(root) {
+        if created_root {
+            if let Err(e) = git::add_to_git_exclude(root) {
+                eprintln!("Warning: Failed to add files to git exclude: {}", e);
+            }
+        } else {
+            eprintln!(
+                "Warning: {} already existed, so it is not removed by nais-env clear",
+                root.display()
+            );
+        }
+    }
+
+    Ok(mounts)
+}
+
+/// Rewrites in-cluster file paths in environment variable values to the local copies
+///
+/// A value is rewritten when it contains one of the mount paths as a whole path component,
+/// for example `/var/run/secrets/nais.io/kafka/client.keystore.p12` becomes
+/// `<root>/var/run/secrets/nais.io/kafka/client.keystore.p12`. Paths embedded in longer
+/// values, such as JVM options and `file://` URLs, are rewritten as well. When mounts are
+/// nested, the longest matching mount path wins.
+///
+/// # Arguments
+///
+/// * `env_vars` - Environment variables to rewrite in place
+/// * `mounts` - Map from in-cluster mount path to local directory
+pub fn rewrite_mount_paths(
+    env_vars: &mut BTreeMap<String, String>,
+    mounts: &BTreeMap<String, PathBuf>,
+) {
+    let mut sorted: Vec<(&str, String)> = mounts
+        .iter()
+        .map(|(mount, local)| {
+            (
+                mount.trim_end_matches('/'),
+                local.to_string_lossy().to_string(),
+            )
+        })
+        .filter(|(mount, _)| !mount.is_empty())
+        .collect();
+    sorted.sort_by_key(|(mount, _)| std::cmp::Reverse(mount.len()));
+
+    for value in env_vars.values_mut() {
+        *value = rewrite_value(value, &sorted);
+    }
+}
+
+/// Replaces mount paths in a single value, see `rewrite_mount_paths`
+fn rewrite_value(value: &str, mounts: &[(&str, String)]) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    'outer: while let Some(next) = rest.chars().next() {
+        // A path starts after a character that can not be part of it, or after the `//` of
+        // an URL such as `file:///var/run/...`
+        let at_path_start =
+            !result.chars().next_back().is_some_and(is_path_char) || result.ends_with("://");
+
+        if at_path_start {
+            for (mount, local) in mounts {
+                if let Some(after) = rest.strip_prefix(mount)
+                    && (after.starts_with('/') || !after.chars().next().is_some_and(is_path_char))
+                {
+                    result.push_str(local);
+                    rest = after;
+                    continue 'outer;
+                }
+            }
+        }
+
+        result.push(next);
+        rest = &rest[next.len_utf8()..];
+    }
+
+    result
+}
+
+/// Characters that can be part of a path component
+fn is_path_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '/' | '.' | '-' | '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mount_paths_are_placed_below_the_root() {
+        assert_eq!(
+            local_mount_path(Path::new("files"), "/var/run/secrets/certs"),
+            Some(PathBuf::from("files/var/run/secrets/certs"))
+        );
+        assert_eq!(
+            local_mount_path(Path::new("files"), "./config/"),
+            Some(PathBuf::from("files/config"))
+        );
+    }
+
+    #[test]
+    fn mount_paths_with_parent_components_are_rejected() {
+        let root = Path::new("files");
+        assert_eq!(local_mount_path(root, "/../../home/user/.ssh"), None);
+        assert_eq!(local_mount_path(root, "/var/run/../../../etc"), None);
+        assert_eq!(local_mount_path(root, ".."), None);
+    }
+
+    fn rewrite(value: &str, mounts: &[(&str, &str)]) -> String {
+        let mounts: BTreeMap<String, PathBuf> = mounts
+            .iter()
+            .map(|(mount, local)| (mount.to_string(), PathBuf::from(local)))
+            .collect();
+        let mut env_vars = BTreeMap::from([(String::from("KEY"), value.to_string())]);
+        rewrite_mount_paths(&mut env_vars, &mounts);
+        env_vars.remove("KEY").unwrap()
+    }
+
+    const SERVICEUSER: (&str, &str) = ("/secrets/serviceuser", "/local/serviceuser");
+
+    #[test]
+    fn exact_mount_path_is_rewritten() {
+        assert_eq!(
+            rewrite("/secrets/serviceuser", &[SERVICEUSER]),
+            "/local/serviceuser"
+        );
+        assert_eq!(
+            rewrite("/secrets/serviceuser/", &[SERVICEUSER]),
+            "/local/serviceuser/"
+        );
+    }
+
+    #[test]
+    fn files_below_the_mount_path_are_rewritten() {
+        assert_eq!(
+            rewrite("/secrets/serviceuser/username", &[SERVICEUSER]),
+            "/local/serviceuser/username"
+        );
+    }
+
+    #[test]
+    fn sibling_with_a_shared_prefix_is_not_rewritten() {
+        assert_eq!(
+            rewrite("/secrets/serviceuser-old/username", &[SERVICEUSER]),
+            "/secrets/serviceuser-old/username"
+        );
+        assert_eq!(
+            rewrite("/secrets/serviceusers", &[SERVICEUSER]),
+            "/secrets/serviceusers"
+        );
+    }
+
+    #[test]
+    fn mount_path_inside_another_path_is_not_rewritten() {
+        assert_eq!(
+            rewrite("/opt/secrets/serviceuser/username", &[SERVICEUSER]),
+            "/opt/secrets/serviceuser/username"
+        );
+    }
+
+    #[test]
+    fn longest_nested_mount_wins() {
+        let mounts = [
+            ("/var/run/secrets", "/local/secrets"),
+            ("/var/run/secrets/nais.io/kafka", "/local/kafka"),
+        ];
+
+        assert_eq!(
+            rewrite(
+                "/var/run/secrets/nais.io/kafka/client.keystore.p12",
+                &mounts
+            ),
+            "/local/kafka/client.keystore.p12"
+        );
+        assert_eq!(
+            rewrite("/var/run/secrets/other/key", &mounts),
+            "/local/secrets/other/key"
+        );
+    }
+
+    #[test]
+    fn jvm_options_are_rewritten() {
+        let mounts = [("/var/run/secrets/nais.io/kafka", "/local/kafka")];
+
+        assert_eq!(
+            rewrite(
+                "-Xmx512m -Djavax.net.ssl.trustStore=/var/run/secrets/nais.io/kafka/client.truststore.jks -Dother=x",
+                &mounts
+            ),
+            "-Xmx512m -Djavax.net.ssl.trustStore=/local/kafka/client.truststore.jks -Dother=x"
+        );
+    }
+
+    #[test]
+    fn file_urls_are_rewritten() {
+        let mounts = [("/var/run/secrets/nais.io/kafka", "/local/kafka")];
+
+        assert_eq!(
+            rewrite("file:///var/run/secrets/nais.io/kafka/ca.pem", &mounts),
+            "file:///local/kafka/ca.pem"
+        );
+    }
+
+    #[test]
+    fn several_paths_in_one_value_are_rewritten() {
+        assert_eq!(
+            rewrite(
+                "/secrets/serviceuser/username:/secrets/serviceuser/password",
+                &[SERVICEUSER]
+            ),
+            "/local/serviceuser/username:/local/serviceuser/password"
+        );
+    }
+}
@@ -55,15 +55,11 @@ pub fn add_to_git_exclude<P: AsRef<std::path::Path>>(file_path: P) -> std::io::R
         _ => return Ok(()),
     };
 
-    // Get the relative path from repo root
+    // Get the relative path from repo root. Files outside the repository are not added, since
+    // `nais-env clear` deletes everything listed relative to the repository root.
     let relative_path = match get_relative_path_from_repo_root(&file_path) {
         Some(path) => path,
-        None => file_path
-            .as_ref()
-            .file_name()
-            .unwrap_or_default()
-            .to_string_lossy()
-            .to_string(),
+        None => return Ok(()),
     };
 
     // Read current content
@@ -97,48 +93,84 @@ pub fn add_to_git_exclude<P: AsRef<std::path::Path>>(file_path: P) -> std::io::R
     Ok(())
 }
 
-/// Gets the path to the repository root
+/// Gets the root of the git repository containing the current directory
 ///
 /// # Returns
 ///
-/// * `String` - Path to the repository root
-pub fn get_repo_root() -> String {
-    let output = std::process::Command::new("git")
+/// * `Option<std::path::PathBuf>` - Path to the repository root, None if not in a git repo
+pub fn get_repo_root() -> Option<std::path::PathBuf> {
+    std::process::Command::new("git")
         .args(["rev-parse", "--show-toplevel"])
         .output()
-        .expect("Failed to execute git command to find repo root");
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|root| std::path::PathBuf::from(root.trim()))
+}
 
-    if !output.status.success() {
-        panic!("Failed to get git repository root");
+/// Checks if a path is already listed in git's exclude file
+///
+/// # Arguments
+///
+/// * `file_path` - Path to the file or directory
+///
+/// # Returns
+///
+/// * `bool` - True if the path is inside the repository and listed in the exclude file
+pub fn is_in_git_exclude<P: AsRef<std::path::Path>>(file_path: P) -> bool {
+    if !is_in_git_repo() {
+        return false;
     }
 
-    String::from_utf8(output.stdout)
-        .expect("Invalid UTF-8 in git repo path")
-        .trim()
-        .to_string()
+    let (Some(exclude_path), Some(relative_path)) = (
+        get_git_exclude_path(),
+        get_relative_path_from_repo_root(file_path),
+    ) else {
+        return false;
+    };
+
+    std::fs::read_to_string(exclude_path)
+        .map(|content| content.lines().any(|line| line == relative_path))
+        .unwrap_or(false)
+}
+
+/// Checks whether git tracks any files at the given path
+///
+/// # Arguments
+///
+/// * `path` - Path to a file or directory
+///
+/// # Returns
+///
+/// * `bool` - True if the path is, or contains, a tracked file
+pub fn has_tracked_files<P: AsRef<std::path::Path>>(path: P) -> bool {
+    std::process::Command::new("git")
+        .arg("ls-files")
+        .arg("--")
+        .arg(path.as_ref())
+        .output()
+        .map(|output| !output.stdout.is_empty())
+        .unwrap_or(false)
 }
 
 /// Gets the path to a file relative to repository root
 ///
+/// The file must exist, since symlinks and `..` are resolved before comparing the paths.
+///
 /// # Arguments
 ///
 /// * `file_path` - Path to the file
 ///
 /// # Returns
 ///
-/// * `Option<String>` - Relative path if successful, None otherwise
+/// * `Option<String>` - Relative path if the file is inside the repository, None otherwise
 fn get_relative_path_from_repo_root<P: AsRef<std::path::Path>>(file_path: P) -> Option<String> {
-    let repo_root = get_repo_root();
-    let repo_root_path = std::path::Path::new(&repo_root);
-
-    let absolute_path = if file_path.as_ref().is_absolute() {
-        file_path.as_ref().to_path_buf()
-    } else {
-        std::env::current_dir().ok()?.join(file_path.as_ref())
-    };
+    let repo_root = std::fs::canonicalize(get_repo_root()?).ok()?;
+    let absolute_path = std::fs::canonicalize(file_path).ok()?;
 
     absolute_path
-        .strip_prefix(repo_root_path)
+        .strip_prefix(&repo_root)
         .ok()
+        .filter(|rel_path| !rel_path.as_os_str().is_empty())
         .map(|rel_path| rel_path.to_string_lossy().to_string())
 }
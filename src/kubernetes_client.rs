@@ -1,12 +1,124 @@
-use k8s_openapi::api::core::v1::Secret;
-use kube::{Api, Client, Config};
-use std::{collections::BTreeMap, str};
+use k8s_openapi::api::{
+    apps::v1::Deployment,
+    batch::v1::{CronJob, Job},
+    core::v1::{ConfigMap, Pod, PodSpec, Secret},
+};
+use kube::{
+    Api, Client, Config,
+    api::{ApiResource, DynamicObject, GroupVersionKind, ListParams},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    str,
+};
+
+use crate::error::Error;
+use crate::nais::{FilesFrom, NaisConfig};
+use crate::policy::ContextPolicy;
+
+/// Kubernetes contexts whose names differ from the name of the cluster they point at.
+const CONTEXT_CLUSTER_NAMES: [(&str, &str); 1] = [("nais-dev", "dev-gcp")];
+
+/// Derives the NAIS cluster name from a Kubernetes context name.
+///
+/// Most contexts are named after their cluster, so the context name is used
+/// as-is unless it is a known alias.
+///
+/// # Example
+///
+/// ```
+/// assert_eq!(cluster_name_from_context("nais-dev"), "dev-gcp");
+/// assert_eq!(cluster_name_from_context("dev-fss"), "dev-fss");
+/// ```
+pub fn cluster_name_from_context(context: &str) -> String {
+    CONTEXT_CLUSTER_NAMES
+        .iter()
+        .find(|(alias, _)| *alias == context)
+        .map(|(_, cluster)| cluster.to_string())
+        .unwrap_or_else(|| context.to_string())
+}
+
+/// Fields from a running pod that can be referenced through the downward API.
+#[derive(Debug, Clone, Default)]
+pub struct PodInfo {
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    pub node_name: Option<String>,
+    pub pod_ip: Option<String>,
+}
+
+/// The result of resolving the `envFrom` sources or the `valueFrom` key references of a
+/// pod template.
+#[derive(Debug, Clone, Default)]
+pub struct ResolvedEnv {
+    /// All resolved environment variables
+    pub env_vars: BTreeMap<String, String>,
+    /// Names of the secrets the variables were read from, in the order they were applied
+    pub secrets: Vec<String>,
+    /// A description of every source that could not be fetched
+    pub failures: Vec<String>,
+}
+
+/// A source referenced in the `envFrom` section of a container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvFromSource {
+    Secret {
+        name: String,
+        prefix: Option<String>,
+        optional: bool,
+    },
+    ConfigMap {
+        name: String,
+        prefix: Option<String>,
+        optional: bool,
+    },
+}
+
+impl EnvFromSource {
+    /// Returns the name of the referenced secret or config map.
+    pub fn name(&self) -> &str {
+        match self {
+            EnvFromSource::Secret { name, .. } | EnvFromSource::ConfigMap { name, .. } => name,
+        }
+    }
+
+    /// Returns a short description of the source kind, used in messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EnvFromSource::Secret { .. } => "secret",
+            EnvFromSource::ConfigMap { .. } => "configmap",
+        }
+    }
+}
+
+/// The kind of Kubernetes workload that runs the application's pods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Workload {
+    /// An Application runs as a Deployment
+    Deployment,
+    /// A Naisjob with a schedule runs as a CronJob
+    CronJob,
+    /// A Naisjob without a schedule runs as a single Job
+    Job,
+}
+
+impl std::fmt::Display for Workload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Workload::Deployment => write!(f, "deployment"),
+            Workload::CronJob => write!(f, "cronjob"),
+            Workload::Job => write!(f, "job"),
+        }
+    }
+}
 
 pub struct KubernetesClient {
     client: Client,
     deployment: String,
+    workload: Workload,
     namespace: String,
     context: String,
+    policy: ContextPolicy,
 }
 
 impl KubernetesClient {
@@ -15,7 +127,8 @@ impl KubernetesClient {
     /// # Arguments
     ///
     /// * `namespace` - The Kubernetes namespace to operate within
-    /// * `deployment` - The name of the deployment to target
+    /// * `deployment` - The name of the deployment, cronjob or job to target
+    /// * `workload` - The kind of workload the pods are defined in
     /// * `context` - The Kubernetes context to use (e.g., 'nais-dev' or 'dev-fss')
     ///
     /// # Returns
@@ -24,32 +137,64 @@ impl KubernetesClient {
     ///
     /// # Errors
     ///
-    /// Returns a `kube::Error` if the Kubernetes configuration cannot be loaded or if the client
+    /// Returns an error if the Kubernetes configuration cannot be loaded or if the client
     /// cannot be created from the configuration.
     pub async fn new(
         namespace: String,
         deployment: String,
+        workload: Workload,
         context: String,
-    ) -> Result<Self, kube::Error> {
+        policy: &ContextPolicy,
+    ) -> Result<Self, Error> {
         let mut config = Config::from_kubeconfig(&kube::config::KubeConfigOptions {
             context: Some(context.clone()),
             ..Default::default()
         })
         .await
-        .expect("Failed to load kubernetes config");
+        .map_err(|e| Error::from_kubeconfig(&context, e))?;
+
+        // The context name alone can be pointed anywhere, so check where it actually leads
+        policy.check_server(&context, &config.cluster_url.to_string())?;
 
         config.default_namespace = namespace.clone();
 
-        let client = Client::try_from(config)?;
+        let client = Client::try_from(config)
+            .map_err(|e| Error::from_kube(String::from("Failed to create kubernetes client"), e))?;
 
         Ok(Self {
             client,
             deployment,
+            workload,
             namespace,
             context,
+            policy: policy.clone(),
         })
     }
 
+    /// Fails with `Error::Policy` if the secret is on the deny-list for the context.
+    fn check_secret_allowed(&self, secret_name: &str) -> Result<(), Error> {
+        if self.policy.is_secret_denied(secret_name) {
+            return Err(Error::Policy(format!(
+                "Secret '{}' is denied by the policy for context '{}'",
+                secret_name, self.context
+            )));
+        }
+        Ok(())
+    }
+
+    /// Converts a Kubernetes API error into an `Error` describing what was being fetched.
+    fn api_error(&self, resource: String) -> impl Fn(kube::Error) -> Error + '_ {
+        move |e| {
+            Error::from_kube(
+                format!(
+                    "Failed to get {} in namespace '{}' with context '{}'",
+                    resource, self.namespace, self.context
+                ),
+                e,
+            )
+        }
+    }
+
     /// Retrieves a Kubernetes secret and converts its data into a key-value map.
     ///
     /// # Arguments
@@ -65,13 +210,13 @@ impl KubernetesClient {
     ///
     /// Returns an error if the secret cannot be retrieved from the Kubernetes API
     /// or if there's an issue parsing the secret data.
-    pub async fn get_secret(
-        &self,
-        secret_name: &str,
-    ) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+    pub async fn get_secret(&self, secret_name: &str) -> Result<BTreeMap<String, String>, Error> {
+        self.check_secret_allowed(secret_name)?;
+
         let secret: Secret = Api::default_namespaced(self.client.clone())
             .get(secret_name)
-            .await?;
+            .await
+            .map_err(self.api_error(format!("secret '{}'", secret_name)))?;
 
         let env_vars: BTreeMap<String, String> = if let Some(data) = &secret.data {
             data.iter()
@@ -89,54 +234,542 @@ impl KubernetesClient {
         Ok(env_vars)
     }
 
-    /// This method fetches the deployment configuration from Kubernetes and extracts
-    /// secret names that are referenced in the `envFrom` section of any container
-    /// in the deployment. These secrets typically contain environment variables that
-    /// should be loaded into the container.
+    /// Retrieves a Kubernetes config map and converts its data into a key-value map.
+    ///
+    /// Both `data` and `binaryData` are included. Binary values that are not valid
+    /// UTF-8 are converted to empty strings, the same way as for secrets.
+    ///
+    /// # Arguments
+    ///
+    /// * `config_map_name` - The name of the config map to retrieve
     ///
     /// # Returns
     ///
-    /// A sorted vector of unique secret names referenced by the deployment.
+    /// A map containing all key-value pairs from the config map.
+    /// If the config map exists but has no data, an empty map is returned.
     ///
     /// # Errors
     ///
-    /// Returns an error if fetching the deployment information fails or if there's
+    /// Returns an error if the config map cannot be retrieved from the Kubernetes API.
+    pub async fn get_config_map(
+        &self,
+        config_map_name: &str,
+    ) -> Result<BTreeMap<String, String>, Error> {
+        let config_map: ConfigMap = Api::default_namespaced(self.client.clone())
+            .get(config_map_name)
+            .await
+            .map_err(self.api_error(format!("configmap '{}'", config_map_name)))?;
+
+        let mut env_vars = BTreeMap::new();
+
+        if let Some(binary_data) = &config_map.binary_data {
+            for (key, value) in binary_data {
+                env_vars.insert(
+                    key.clone(),
+                    String::from_utf8(value.0.clone()).unwrap_or_default(),
+                );
+            }
+        }
+
+        if let Some(data) = config_map.data {
+            env_vars.extend(data);
+        }
+
+        Ok(env_vars)
+    }
+
+    /// Retrieves the raw contents of a Kubernetes secret, keeping binary values intact.
+    ///
+    /// This is used when secrets are mounted as files, where values such as keystores
+    /// are not valid UTF-8.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret_name` - The name of the secret to retrieve
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the secret cannot be retrieved from the Kubernetes API.
+    pub async fn get_secret_files(
+        &self,
+        secret_name: &str,
+    ) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+        self.check_secret_allowed(secret_name)?;
+
+        let secret: Secret = Api::default_namespaced(self.client.clone())
+            .get(secret_name)
+            .await
+            .map_err(self.api_error(format!("secret '{}'", secret_name)))?;
+
+        Ok(secret
+            .data
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| (key, value.0))
+            .collect())
+    }
+
+    /// Retrieves the raw contents of a Kubernetes config map, including `binaryData`.
+    ///
+    /// # Arguments
+    ///
+    /// * `config_map_name` - The name of the config map to retrieve
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the config map cannot be retrieved from the Kubernetes API.
+    pub async fn get_config_map_files(
+        &self,
+        config_map_name: &str,
+    ) -> Result<BTreeMap<String, Vec<u8>>, Error> {
+        let config_map: ConfigMap = Api::default_namespaced(self.client.clone())
+            .get(config_map_name)
+            .await
+            .map_err(self.api_error(format!("configmap '{}'", config_map_name)))?;
+
+        let mut files: BTreeMap<String, Vec<u8>> = config_map
+            .binary_data
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| (key, value.0))
+            .collect();
+
+        for (key, value) in config_map.data.unwrap_or_default() {
+            files.insert(key, value.into_bytes());
+        }
+
+        Ok(files)
+    }
+
+    /// Fetches the pod template spec of the workload.
+    ///
+    /// For a Deployment or Job this is the pod template of the resource itself, for a
+    /// CronJob it is the pod template inside its job template. The workload is fetched once,
+    /// and the spec is passed to `env_from_sources`, `resolve_env_from`,
+    /// `resolve_env_key_refs` and `volume_mounts`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if fetching the workload fails or if there's
     /// an issue accessing the Kubernetes API.
-    pub async fn get_env_from_secrets(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let api =
-            Api::<k8s_openapi::api::apps::v1::Deployment>::default_namespaced(self.client.clone());
+    pub async fn get_pod_spec(&self) -> Result<PodSpec, Error> {
+        let map_err = self.api_error(format!("{} '{}'", self.workload, self.deployment));
 
-        let deployment = api.get(&self.deployment).await.map_err(|e| {
-            Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!(
-                    "Failed to get deployment '{}' in namespace '{}' with context '{}': {}",
-                    self.deployment, self.namespace, self.context, e
-                ),
-            )) as Box<dyn std::error::Error>
-        })?;
-
-        let mut secret_names = Vec::new();
-
-        if let Some(spec) = deployment.spec {
-            let template = spec.template;
-            if let Some(pod_spec) = template.spec {
-                for container in pod_spec.containers {
-                    if let Some(env_from) = container.env_from {
-                        for env_source in env_from {
-                            if let Some(secret_ref) = env_source.secret_ref {
-                                secret_names.push(secret_ref.name);
-                            }
+        let pod_spec = match self.workload {
+            Workload::Deployment => Api::<Deployment>::default_namespaced(self.client.clone())
+                .get(&self.deployment)
+                .await
+                .map_err(map_err)?
+                .spec
+                .and_then(|spec| spec.template.spec),
+            Workload::CronJob => Api::<CronJob>::default_namespaced(self.client.clone())
+                .get(&self.deployment)
+                .await
+                .map_err(map_err)?
+                .spec
+                .and_then(|spec| spec.job_template.spec)
+                .and_then(|spec| spec.template.spec),
+            Workload::Job => Api::<Job>::default_namespaced(self.client.clone())
+                .get(&self.deployment)
+                .await
+                .map_err(map_err)?
+                .spec
+                .and_then(|spec| spec.template.spec),
+        };
+
+        Ok(pod_spec.unwrap_or_default())
+    }
+
+    /// Resolves every secret and config map referenced by `envFrom` into environment variables.
+    ///
+    /// Sources are applied in the order they appear in the deployment, so keys from later
+    /// sources override keys from earlier ones, the same way Kubernetes does it. A `prefix`
+    /// on the `envFrom` entry is prepended to every key from that source.
+    ///
+    /// Sources that cannot be fetched are skipped and reported as failures, unless they are
+    /// marked as `optional` in which case they are skipped silently.
+    ///
+    /// # Arguments
+    ///
+    /// * `pod_spec` - The pod template spec from `get_pod_spec`
+    ///
+    /// # Returns
+    ///
+    /// The resolved environment variables, the secrets they were read from, and a
+    /// description of every source that could not be fetched.
+    pub async fn resolve_env_from(&self, pod_spec: &PodSpec) -> ResolvedEnv {
+        let mut resolved = ResolvedEnv::default();
+
+        for source in env_from_sources(pod_spec) {
+            let (result, prefix, optional) = match &source {
+                EnvFromSource::Secret {
+                    name,
+                    prefix,
+                    optional,
+                } => (self.get_secret(name).await, prefix, *optional),
+                EnvFromSource::ConfigMap {
+                    name,
+                    prefix,
+                    optional,
+                } => (self.get_config_map(name).await, prefix, *optional),
+            };
+
+            match result {
+                Ok(values) => {
+                    let prefix = prefix.as_deref().unwrap_or_default();
+                    for (key, value) in values {
+                        resolved
+                            .env_vars
+                            .insert(format!("{}{}", prefix, key), value);
+                    }
+                    if let EnvFromSource::Secret { name, .. } = &source
+                        && !resolved.secrets.contains(name)
+                    {
+                        resolved.secrets.push(name.clone());
+                    }
+                }
+                Err(Error::Policy(message)) => eprintln!("Skipping {}", message),
+                Err(_) if optional => {}
+                Err(e) => {
+                    resolved
+                        .failures
+                        .push(format!("{} {}: {}", source.kind(), source.name(), e))
+                }
+            }
+        }
+
+        resolved
+    }
+
+    /// Resolves `valueFrom.secretKeyRef` and `valueFrom.configMapKeyRef` entries in the `env`
+    /// section of the deployment's containers.
+    ///
+    /// Each referenced secret or config map is fetched once, and the single key is looked up
+    /// in it. Entries marked as `optional` are skipped silently when the source or key is
+    /// missing, other missing entries are skipped and reported as failures.
+    ///
+    /// # Arguments
+    ///
+    /// * `pod_spec` - The pod template spec from `get_pod_spec`
+    ///
+    /// # Returns
+    ///
+    /// The resolved environment variables, the secrets they were read from, and a
+    /// description of every entry that could not be resolved.
+    pub async fn resolve_env_key_refs(&self, pod_spec: &PodSpec) -> ResolvedEnv {
+        let mut resolved = ResolvedEnv::default();
+        let mut secrets: HashMap<String, Result<BTreeMap<String, String>, Error>> = HashMap::new();
+        let mut config_maps: HashMap<String, Result<BTreeMap<String, String>, Error>> =
+            HashMap::new();
+
+        for container in &pod_spec.containers {
+            for env_var in container.env.clone().unwrap_or_default() {
+                let Some(value_from) = env_var.value_from else {
+                    continue;
+                };
+
+                let (kind, source_name, key, optional, values) =
+                    if let Some(secret_key_ref) = value_from.secret_key_ref {
+                        if !secrets.contains_key(&secret_key_ref.name) {
+                            let fetched = self.get_secret(&secret_key_ref.name).await;
+                            secrets.insert(secret_key_ref.name.clone(), fetched);
+                        }
+                        let values = &secrets[&secret_key_ref.name];
+                        (
+                            "secret",
+                            secret_key_ref.name,
+                            secret_key_ref.key,
+                            secret_key_ref.optional.unwrap_or(false),
+                            values,
+                        )
+                    } else if let Some(config_map_key_ref) = value_from.config_map_key_ref {
+                        if !config_maps.contains_key(&config_map_key_ref.name) {
+                            let fetched = self.get_config_map(&config_map_key_ref.name).await;
+                            config_maps.insert(config_map_key_ref.name.clone(), fetched);
+                        }
+                        let values = &config_maps[&config_map_key_ref.name];
+                        (
+                            "configmap",
+                            config_map_key_ref.name,
+                            config_map_key_ref.key,
+                            config_map_key_ref.optional.unwrap_or(false),
+                            values,
+                        )
+                    } else {
+                        continue;
+                    };
+
+                match values.as_ref().map(|values| values.get(&key)) {
+                    Ok(Some(value)) => {
+                        resolved.env_vars.insert(env_var.name, value.clone());
+                        if kind == "secret" && !resolved.secrets.contains(&source_name) {
+                            resolved.secrets.push(source_name);
                         }
                     }
+                    Err(Error::Policy(message)) => {
+                        eprintln!("Skipping {} (referenced by {})", message, env_var.name)
+                    }
+                    _ if optional => {}
+                    Ok(None) => resolved.failures.push(format!(
+                        "key '{}' in {} {} (referenced by {}): not found",
+                        key, kind, source_name, env_var.name
+                    )),
+                    Err(e) => resolved.failures.push(format!(
+                        "{} {} (referenced by {}): {}",
+                        kind, source_name, env_var.name, e
+                    )),
                 }
             }
         }
 
-        // Remove duplicates
-        secret_names.sort();
-        secret_names.dedup();
+        resolved
+    }
+
+    /// Finds a running pod belonging to the deployment.
+    ///
+    /// Pods are looked up with the `app` label that naiserator sets on every pod.
+    ///
+    /// # Returns
+    ///
+    /// The fields of the first running pod, or `None` if no running pod was found.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the pods cannot be listed.
+    pub async fn get_pod_info(&self) -> Result<Option<PodInfo>, Error> {
+        let pods = Api::<Pod>::default_namespaced(self.client.clone())
+            .list(&ListParams::default().labels(&format!("app={}", self.deployment)))
+            .await
+            .map_err(self.api_error(format!("pods for '{}'", self.deployment)))?;
+
+        let running = pods.items.into_iter().find(|pod| {
+            pod.status
+                .as_ref()
+                .and_then(|status| status.phase.as_deref())
+                == Some("Running")
+        });
+
+        Ok(running.map(|pod| PodInfo {
+            name: pod.metadata.name.unwrap_or_default(),
+            labels: pod.metadata.labels.unwrap_or_default(),
+            node_name: pod.spec.and_then(|spec| spec.node_name),
+            pod_ip: pod.status.and_then(|status| status.pod_ip),
+        }))
+    }
+
+    /// Fetches the `nais.io/v1alpha1` Application resource for the deployment from the cluster.
+    ///
+    /// This makes it possible to use nais-env without a local nais.yaml, for example when
+    /// looking at another team's application.
+    ///
+    /// # Returns
+    ///
+    /// The Application parsed into the same model as a local nais.yaml.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the Application cannot be fetched or does not match the expected structure.
+    pub async fn get_application(&self) -> Result<NaisConfig, Error> {
+        let resource =
+            ApiResource::from_gvk(&GroupVersionKind::gvk("nais.io", "v1alpha1", "Application"));
+        let api = Api::<DynamicObject>::default_namespaced_with(self.client.clone(), &resource);
+
+        let application = api
+            .get(&self.deployment)
+            .await
+            .map_err(self.api_error(format!("application '{}'", self.deployment)))?;
+
+        let config: NaisConfig = serde_json::to_value(application)
+            .and_then(serde_json::from_value)
+            .map_err(|e| {
+                Error::Kubernetes(format!(
+                    "Application '{}' does not match the expected structure: {}",
+                    self.deployment, e
+                ))
+            })?;
+
+        Ok(config)
+    }
+}
+
+/// Finds the secret and config map volumes that are mounted into the deployment's containers.
+///
+/// This includes volumes added by naiserator, such as Kafka credentials, which are not
+/// listed under `filesFrom` in nais.yaml. Mounts using `subPath` are skipped, since they
+/// only expose a single file.
+///
+/// # Arguments
+///
+/// * `pod_spec` - The pod template spec from `get_pod_spec`
+///
+/// # Returns
+///
+/// The mounts as `FilesFrom` entries, so they can be written the same way as `filesFrom`.
+pub fn volume_mounts(pod_spec: &PodSpec) -> Vec<FilesFrom> {
+    let volumes = pod_spec.volumes.as_deref().unwrap_or_default();
+    let mut mounts = Vec::new();
+
+    for container in &pod_spec.containers {
+        for volume_mount in container.volume_mounts.iter().flatten() {
+            if volume_mount.sub_path.is_some() {
+                continue;
+            }
+            let Some(volume) = volumes.iter().find(|v| v.name == volume_mount.name) else {
+                continue;
+            };
+
+            let secret = volume.secret.as_ref().and_then(|s| s.secret_name.clone());
+            let configmap = volume.config_map.as_ref().map(|c| c.name.clone());
+            if secret.is_none() && configmap.is_none() {
+                continue;
+            }
+
+            if !mounts
+                .iter()
+                .any(|m: &FilesFrom| m.mountPath == volume_mount.mount_path)
+            {
+                mounts.push(FilesFrom {
+                    configmap,
+                    secret,
+                    emptyDir: None,
+                    persistentVolumeClaim: None,
+                    mountPath: volume_mount.mount_path.clone(),
+                });
+            }
+        }
+    }
+
+    mounts
+}
+
+/// Extracts the secrets and config maps that are referenced in the `envFrom` section of
+/// any container in the pod template.
+///
+/// # Arguments
+///
+/// * `pod_spec` - The pod template spec from `get_pod_spec`
+///
+/// # Returns
+///
+/// The referenced sources in the order they appear in the deployment, without duplicates.
+/// The order matters, since later sources override keys from earlier ones, so a source
+/// that is referenced more than once is kept at its last position.
+pub fn env_from_sources(pod_spec: &PodSpec) -> Vec<EnvFromSource> {
+    let mut sources = Vec::new();
+
+    for container in &pod_spec.containers {
+        for env_source in container.env_from.iter().flatten() {
+            let prefix = &env_source.prefix;
+            if let Some(secret_ref) = &env_source.secret_ref {
+                sources.push(EnvFromSource::Secret {
+                    name: secret_ref.name.clone(),
+                    prefix: prefix.clone(),
+                    optional: secret_ref.optional.unwrap_or(false),
+                });
+            }
+            if let Some(config_map_ref) = &env_source.config_map_ref {
+                sources.push(EnvFromSource::ConfigMap {
+                    name: config_map_ref.name.clone(),
+                    prefix: prefix.clone(),
+                    optional: config_map_ref.optional.unwrap_or(false),
+                });
+            }
+        }
+    }
+
+    // Remove duplicates while keeping the last occurrence, which is the one that wins
+    let mut unique = Vec::new();
+    for source in sources.into_iter().rev() {
+        if !unique.contains(&source) {
+            unique.push(source);
+        }
+    }
+    unique.reverse();
+
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::api::core::v1::{
+        ConfigMapEnvSource, Container, EnvFromSource as EnvFrom, SecretEnvSource,
+    };
+
+    fn secret_ref(name: &str) -> EnvFrom {
+        EnvFrom {
+            secret_ref: Some(SecretEnvSource {
+                name: name.to_string(),
+                optional: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn secret(name: &str) -> EnvFromSource {
+        EnvFromSource::Secret {
+            name: name.to_string(),
+            prefix: None,
+            optional: false,
+        }
+    }
+
+    fn pod_spec(env_from: Vec<EnvFrom>) -> PodSpec {
+        PodSpec {
+            containers: vec![Container {
+                name: String::from("app"),
+                env_from: Some(env_from),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn env_from_sources_keep_their_order_and_prefix() {
+        let config_map = EnvFrom {
+            config_map_ref: Some(ConfigMapEnvSource {
+                name: String::from("config"),
+                optional: Some(true),
+            }),
+            prefix: Some(String::from("CFG_")),
+            ..Default::default()
+        };
+        let sources = env_from_sources(&pod_spec(vec![config_map, secret_ref("app")]));
+
+        assert_eq!(
+            sources,
+            vec![
+                EnvFromSource::ConfigMap {
+                    name: String::from("config"),
+                    prefix: Some(String::from("CFG_")),
+                    optional: true,
+                },
+                secret("app"),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_env_from_sources_are_kept_at_their_last_position() {
+        let sources = env_from_sources(&pod_spec(vec![
+            secret_ref("a"),
+            secret_ref("b"),
+            secret_ref("a"),
+        ]));
+
+        assert_eq!(sources, vec![secret("b"), secret("a")]);
+    }
+
+    #[test]
+    fn known_context_aliases_map_to_their_cluster() {
+        assert_eq!(cluster_name_from_context("nais-dev"), "dev-gcp");
+    }
 
-        Ok(secret_names)
+    #[test]
+    fn other_contexts_are_used_as_the_cluster_name() {
+        assert_eq!(cluster_name_from_context("dev-fss"), "dev-fss");
+        assert_eq!(cluster_name_from_context("prod-gcp"), "prod-gcp");
+        assert_eq!(cluster_name_from_context("minikube"), "minikube");
     }
 }
@@ -1,64 +1,173 @@
-use std::{env, io, process::Command};
+use std::io;
 
 use clap::{CommandFactory, Parser, Subcommand};
 use clap_complete::{
     generate,
     shells::{Bash, Fish, PowerShell, Zsh},
 };
+mod discovery;
+mod dotenv;
 mod env_file;
+mod error;
+mod expansion;
+mod files_from;
 mod git;
 mod kubernetes_client;
 mod nais;
+mod policy;
+mod project;
+mod shell;
 mod yaml_vars;
 
+use error::Error;
+
 /// Set up configuration from Nais locally
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Save Nais configuration to file
-    #[arg(short, long)]
-    file: Option<String>,
-
-    /// Files with environment variables that override the ones from Kubernetes
-    #[arg(short, long)]
-    overrides: Option<Vec<String>>,
-
-    /// Path to nais.yaml
-    #[arg(short, long)]
-    config: Option<String>,
+    #[command(flatten)]
+    source: SourceArgs,
 
-    /// YAML file containing variables
+    /// Save Nais configuration to file [deprecated: use `nais-env fetch --file`]
     #[arg(short, long)]
-    variables: Option<String>,
+    file: Option<String>,
 
-    /// Print processed template after variable substitution
+    /// Print processed template after variable substitution [deprecated: use `nais-env template`]
     #[arg(long)]
     print_template: bool,
 
-    /// Print secrets
+    /// Print secrets [deprecated: use `nais-env print`]
     #[arg(short, long)]
     print: bool,
 
-    /// Spawn shell with secrets as environment variables or run specified command
+    /// Spawn shell with secrets as environment variables or run specified command [deprecated: use `nais-env shell` or `nais-env run`]
     #[arg(short, long, default_missing_value = "SHELL" , num_args = 0..=1)]
     shell: Option<String>,
 
-    /// Clear all files added by nais-env (must be in git repository)
+    /// Clear all files added by nais-env (must be in git repository) [deprecated: use `nais-env clear`]
     #[arg(long)]
     clear_files: bool,
 
-    /// Kubernetes context to use (only 'nais-dev' or 'dev-fss' are supported), defaults to nais-dev
-    #[arg(long, default_value = "nais-dev")]
-    context: String,
-
     /// Subcommands
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+/// Where the environment comes from, shared by all subcommands
+#[derive(clap::Args, Debug)]
+struct SourceArgs {
+    /// Files with environment variables that override the ones from Kubernetes
+    #[arg(short, long, global = true)]
+    overrides: Option<Vec<String>>,
+
+    /// Path to nais.yaml, found in .nais/ in the git repository when not given
+    #[arg(short, long, global = true, conflicts_with = "app")]
+    config: Option<String>,
+
+    /// Environment to use from .nais/, e.g. 'dev' for .nais/vars-dev.yaml or 'my-app/dev'
+    #[arg(short, long, global = true, conflicts_with_all = ["config", "app"])]
+    env: Option<String>,
+
+    /// YAML file containing variables
+    #[arg(short, long, global = true, conflicts_with = "app")]
+    variables: Option<String>,
+
+    /// Name of the Application or Naisjob to use when the config file contains several
+    #[arg(long, global = true, conflicts_with = "app")]
+    select: Option<String>,
+
+    /// Read the Application with this name from the cluster instead of a local nais.yaml
+    #[arg(long, global = true, requires = "namespace")]
+    app: Option<String>,
+
+    /// Namespace of the Application given with --app
+    #[arg(short, long, global = true, requires = "app")]
+    namespace: Option<String>,
+
+    /// Write files from filesFrom secrets and configmaps to a local directory, given as --files=<DIR> (defaults to .nais-env/files)
+    #[arg(
+        long,
+        global = true,
+        value_name = "DIR",
+        default_missing_value = files_from::DEFAULT_FILES_ROOT,
+        num_args = 0..=1,
+        require_equals = true
+    )]
+    files: Option<String>,
+
+    /// Fail without writing anything if any secret, config map or key could not be fetched
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// Kubernetes context to use, must be allowed by the policy (defaults to nais-dev)
+    #[arg(long, global = true)]
+    context: Option<String>,
+
+    /// Profile from .nais-env.yaml to use, defaults to the default profile if there is one
+    #[arg(long, global = true)]
+    profile: Option<String>,
+}
+
 /// Subcommands
-#[derive(Subcommand, Debug)]
+#[derive(Subcommand, Debug, Clone)]
 enum Commands {
+    /// Fetch the environment and save it to a file
+    Fetch {
+        /// File to save to, defaults to the file from the profile or .env
+        #[arg(short, long)]
+        file: Option<String>,
+
+        /// Format to write, inferred from the file extension when not given (defaults to dotenv)
+        #[arg(long, value_enum)]
+        format: Option<env_file::Format>,
+    },
+    /// Print the environment
+    Print {
+        /// Format to print
+        #[arg(long, value_enum, default_value = "dotenv")]
+        format: env_file::Format,
+    },
+    /// Start an interactive shell with the environment
+    ///
+    /// Inside a nais-env shell, the environment of that shell is replaced unless --stack is given.
+    Shell {
+        /// Keep the environment of the current nais-env shell and add this one on top of it
+        #[arg(long)]
+        stack: bool,
+
+        #[command(flatten)]
+        child_env: ChildEnvArgs,
+    },
+    /// Run a program with the environment and exit with its exit code
+    Run {
+        /// Program and arguments to run, e.g. `nais-env run -- ./gradlew test`
+        #[arg(
+            value_name = "PROGRAM",
+            required = true,
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        command: Vec<String>,
+
+        #[command(flatten)]
+        child_env: ChildEnvArgs,
+    },
+    /// Compare the environment with an env file
+    Diff {
+        /// Env file to compare with
+        #[arg(default_value = ".env")]
+        file: String,
+
+        /// Show the values of changed variables, not only their names
+        #[arg(long)]
+        show_values: bool,
+    },
+    /// Print the processed template, without connecting to Kubernetes
+    Template,
+    /// Clear all files added by nais-env (must be in git repository)
+    Clear,
+    /// Print a prompt segment for the current nais-env shell, nothing outside of one
+    Prompt,
     /// Generate shell completion scripts
     Completion {
         /// Shell to generate completions for
@@ -67,6 +176,18 @@ enum Commands {
     },
 }
 
+/// How much of the user's environment the shell or program gets
+#[derive(clap::Args, Debug, Clone, Default)]
+struct ChildEnvArgs {
+    /// Start with only PATH, HOME, TERM and LANG from your environment, plus the fetched variables
+    #[arg(long)]
+    clean_env: bool,
+
+    /// Variable to keep with --clean-env or `cleanEnv` in the profile, can be repeated. `LC_*` keeps all variables starting with `LC_`
+    #[arg(long, value_name = "VAR")]
+    keep_env: Vec<String>,
+}
+
 /// Supported shells for completion
 #[derive(clap::ValueEnum, Clone, Debug)]
 enum CompletionShell {
@@ -80,237 +201,587 @@ enum CompletionShell {
     PowerShell,
 }
 
+/// Kubernetes context used when neither the command line nor the profile sets one
+const DEFAULT_CONTEXT: &str = "nais-dev";
+
+/// File written by `fetch` when neither the command line nor the profile sets one
+const DEFAULT_ENV_FILE: &str = ".env";
+
+/// Values from the profile that are used by the subcommands rather than the source
+#[derive(Debug, Default)]
+struct ProfileDefaults {
+    file: Option<String>,
+    shell: Option<String>,
+    clean_env: bool,
+    keep_env: Vec<String>,
+}
+
+impl ProfileDefaults {
+    /// Returns the variables to keep from the user's environment, or `None` to keep all of them
+    ///
+    /// `--keep-env` is checked against the merged value, so it also works with `cleanEnv` from
+    /// the profile.
+    fn clean_env(&self, args: &ChildEnvArgs) -> Option<Vec<String>> {
+        let clean_env = args.clean_env || self.clean_env;
+        if !clean_env && !args.keep_env.is_empty() {
+            eprintln!(
+                "Warning: --keep-env has no effect without --clean-env or cleanEnv in the profile"
+            );
+        }
+
+        clean_env.then(|| {
+            args.keep_env
+                .iter()
+                .chain(&self.keep_env)
+                .cloned()
+                .collect()
+        })
+    }
+}
+
+/// The loaded configuration, and the client when it was read from the cluster
+struct LoadedConfig {
+    config: nais::NaisConfigLoader,
+    processed_template: Option<String>,
+    display_config: String,
+    client: Option<kubernetes_client::KubernetesClient>,
+}
+
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
+async fn main() {
     let args = Args::parse();
 
-    // Handle completions subcommand
-    if let Some(Commands::Completion { shell }) = &args.command {
-        return generate_completion(shell);
+    match run(args).await {
+        Ok(0) => {}
+        Ok(exit_code) => std::process::exit(exit_code),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(e.exit_code());
+        }
     }
+}
 
-    if args.clear_files {
-        match env_file::clear_env_files() {
-            Ok(_) => std::process::exit(0),
-            Err(e) => {
-                eprintln!("Error clearing env files: {}", e);
-                std::process::exit(1);
+/// Runs nais-env, returning the exit code of the command that was run
+async fn run(args: Args) -> Result<i32, Error> {
+    let mut commands = commands_from_args(&args);
+    let mut source = args.source;
+
+    // Commands that do not need an environment
+    for command in &commands {
+        match command {
+            Commands::Completion { shell } => {
+                return generate_completion(shell)
+                    .map(|_| 0)
+                    .map_err(|e| Error::io("Failed to write completion script", e));
+            }
+            Commands::Prompt => {
+                if let Some(segment) = shell::prompt_segment() {
+                    println!("{}", segment);
+                }
+                return Ok(0);
+            }
+            Commands::Clear => {
+                return env_file::clear_env_files()
+                    .map(|_| 0)
+                    .map_err(|e| Error::io("Failed to clear env files", e));
             }
+            _ => {}
         }
     }
 
-    const ALLOWED_CONTEXTS: [&str; 2] = ["nais-dev", "dev-fss"];
+    let profile = match project::ProjectConfig::find()? {
+        Some(project) => match project.profile(source.profile.as_deref())? {
+            Some(profile) => apply_profile(&mut source, profile),
+            None => ProfileDefaults::default(),
+        },
+        None if source.profile.is_some() => {
+            return Err(Error::Config(error::ConfigError::Profile {
+                path: String::from(project::PROJECT_FILE),
+                message: String::from("No project configuration found in the repository root"),
+            }));
+        }
+        None => ProfileDefaults::default(),
+    };
 
-    // Check if context is allowed, if not exit with error
-    if !ALLOWED_CONTEXTS.contains(&args.context.as_str()) {
-        eprintln!(
-            "Error: Invalid context '{}'. Must be one of: {}",
-            args.context,
-            ALLOWED_CONTEXTS.join(", ")
-        );
-        std::process::exit(1);
+    // Without a command, a profile with a file keeps the old behaviour of fetching to it
+    if commands.is_empty() {
+        if profile.file.is_none() {
+            return Args::command()
+                .print_help()
+                .map(|_| 0)
+                .map_err(|e| Error::io("Failed to print help", e));
+        }
+        commands.push(Commands::Fetch {
+            file: None,
+            format: None,
+        });
+    }
+
+    let context = source
+        .context
+        .clone()
+        .unwrap_or_else(|| String::from(DEFAULT_CONTEXT));
+
+    // Check that the context is allowed before loading anything
+    let policy = policy::Policy::load()?;
+    let context_policy = policy.context(&context)?;
+
+    let loaded = load_config(&mut source, &context, context_policy).await?;
+
+    if commands
+        .iter()
+        .any(|command| matches!(command, Commands::Template))
+    {
+        match &loaded.processed_template {
+            Some(template) => println!("{}", template),
+            None => eprintln!("No template processing was performed (no variables file provided)"),
+        }
+        if commands
+            .iter()
+            .all(|command| matches!(command, Commands::Template))
+        {
+            return Ok(0);
+        }
     }
 
-    let overrides = if let Some(override_files) = args.overrides {
-        env_file::parse_multiple_env_files(override_files)?
+    let overrides = if let Some(override_files) = &source.overrides {
+        env_file::parse_multiple_env_files(override_files.clone())?
     } else {
         std::collections::BTreeMap::new()
     };
 
-    let config_file = match args.config {
-        Some(path) => path,
-        None => {
-            eprintln!("Error: Missing --config[-c] argument. Please provide a path to nais.yaml");
-            std::process::exit(1);
-        }
-    };
+    let fetched_at = k8s_openapi::chrono::Utc::now()
+        .to_rfc3339_opts(k8s_openapi::chrono::SecondsFormat::Secs, true);
+    let fetched = fetch_environment(
+        &source,
+        &loaded.config,
+        loaded.client,
+        context.clone(),
+        context_policy,
+    )
+    .await?;
+    let failures = fetched.failures;
+    let mut all_env_vars = fetched.env_vars;
+
+    // Each failure is reported once here, the final error only counts them
+    let severity = if source.strict { "Error" } else { "Warning" };
+    for failure in &failures {
+        eprintln!("{}: Failed to fetch {}", severity, failure);
+    }
 
-    // Get variable file if provided and store it for later use
-    let variables_file = args.variables.clone();
-    let (nais_config, processed_template) = if let Some(var_file) = args.variables {
-        match yaml_vars::parse_variables_file(&var_file) {
-            Ok(variables) => {
-                let (config_loader, processed) =
-                    nais::NaisConfigLoader::new_with_variables_and_template(
-                        config_file.clone(),
-                        variables,
+    // In strict mode nothing is written when the environment is incomplete
+    if source.strict && !failures.is_empty() {
+        return Err(Error::PartialFetch(failures.len()));
+    }
+
+    // Overrides are applied last, and are never rewritten
+    all_env_vars.extend(overrides);
+
+    let mut exit_code = 0;
+    for command in &commands {
+        match command {
+            Commands::Fetch { file, format } => {
+                let file = file
+                    .clone()
+                    .or_else(|| profile.file.clone())
+                    .unwrap_or_else(|| String::from(DEFAULT_ENV_FILE));
+                let format = format
+                    .or_else(|| env_file::Format::from_path(std::path::Path::new(&file)))
+                    .unwrap_or(env_file::Format::Dotenv);
+                env_file::save_env_vars_to_file(&file, &all_env_vars, format).map_err(|e| {
+                    Error::io(
+                        format!("Failed to save environment variables to {}", file),
+                        e,
                     )
-                    .expect("Could not load config file with variables");
-                (config_loader, Some(processed))
+                })?;
+                println!("Successfully saved environment variables to file: {}", file);
             }
-            Err(e) => {
-                eprintln!("Error parsing variables file '{}': {}", var_file, e);
-                std::process::exit(1);
+            Commands::Print { format } => {
+                format
+                    .writer()
+                    .write(&mut io::stdout().lock(), &all_env_vars)
+                    .map_err(|e| Error::io("Failed to print environment variables", e))?;
             }
-        }
-    } else {
-        (
-            nais::NaisConfigLoader::new(config_file.clone()).expect("Could not load config file"),
-            None,
-        )
-    };
-
-    // Print processed template if requested
-    if args.print_template {
-        if let Some(template) = &processed_template {
-            println!("Processed Template:");
-            println!("{}", template);
-            // If only printing template, exit early without connecting to Kubernetes
-            if args.file.is_none() && !args.print && args.shell.is_none() {
-                return Ok(());
+            Commands::Shell { stack, child_env } => {
+                let metadata = shell::ShellMetadata {
+                    config: loaded.display_config.clone(),
+                    context: context.clone(),
+                    namespace: loaded.config.get_namespace(),
+                    app: loaded.config.get_deployment(),
+                    fetched_at: fetched_at.clone(),
+                    secrets: fetched.secrets.clone(),
+                };
+                exit_code = shell::spawn_interactive_shell(
+                    &all_env_vars,
+                    &metadata,
+                    profile.shell.as_deref(),
+                    *stack,
+                    profile.clean_env(child_env).as_deref(),
+                )
+                .map_err(|e| Error::io("Failed to run shell", e))?;
             }
-        } else {
-            println!("No template processing was performed (no variables file provided)");
+            Commands::Run { command, child_env } => {
+                exit_code = shell::run_command(
+                    &all_env_vars,
+                    command,
+                    profile.clean_env(child_env).as_deref(),
+                )?;
+            }
+            Commands::Diff { file, show_values } => {
+                let existing = env_file::parse_env_file(file)?;
+                print_diff(file, &existing, &all_env_vars, *show_values);
+            }
+            Commands::Template
+            | Commands::Clear
+            | Commands::Prompt
+            | Commands::Completion { .. } => {}
         }
     }
 
-    // Initialize empty collections for secrets
-    let mut collected_secrets = std::collections::BTreeMap::new();
+    // A failing command is more important than an incomplete environment
+    if exit_code != 0 {
+        return Ok(exit_code);
+    }
 
-    let kubernetes_client = kubernetes_client::KubernetesClient::new(
-        nais_config.get_namespace(),
-        nais_config.get_deployment(),
-        args.context,
-    )
-    .await
-    .expect("Failed to create Kubernetes client");
-
-    let secret_keys = kubernetes_client
-        .get_env_from_secrets()
-        .await
-        .expect("Could not get secrets from kubernetes");
-
-    for secret_name in secret_keys {
-        match kubernetes_client.get_secret(&secret_name).await {
-            Ok(secrets) => {
-                for (key, value) in secrets {
-                    collected_secrets.insert(key, value);
-                }
-            }
-            Err(e) => eprintln!("Failed to fetch secret {}: {}", secret_name, e),
-        }
+    // The environment was still used, but scripts should know it was incomplete
+    if !failures.is_empty() {
+        return Err(Error::PartialFetch(failures.len()));
     }
 
-    let nais_config_env_vars = nais_config.get_env_vars();
+    Ok(0)
+}
 
-    // Combine env_vars and secrets into a sorted map
-    let all_env_vars: std::collections::BTreeMap<String, String> = collected_secrets
-        .into_iter()
-        .chain(nais_config_env_vars)
-        .chain(overrides)
-        .collect();
+/// Returns the commands to run, from the subcommand or the deprecated flags
+///
+/// The deprecated flags can be combined, for example `--file .env --print`, and are run in
+/// the order template, fetch, print and shell. A subcommand can not be combined with them.
+fn commands_from_args(args: &Args) -> Vec<Commands> {
+    let mut commands = Vec::new();
+    let mut deprecated = |flag: &str, replacement: &str, command: Commands| {
+        eprintln!(
+            "Warning: {} is deprecated, use `nais-env {}` instead",
+            flag, replacement
+        );
+        commands.push(command);
+    };
 
-    if let Some(file) = args.file {
-        match env_file::save_env_vars_to_file(&file, &all_env_vars) {
-            Ok(_) => println!("Successfully saved environment variables to file: {}", file),
-            Err(e) => eprintln!("Failed to save environment variables to file: {}", e),
-        }
+    if args.clear_files {
+        deprecated("--clear-files", "clear", Commands::Clear);
+    }
+    if args.print_template {
+        deprecated("--print-template", "template", Commands::Template);
+    }
+    if let Some(file) = &args.file {
+        deprecated(
+            "--file",
+            "fetch --file",
+            Commands::Fetch {
+                file: Some(file.clone()),
+                format: None,
+            },
+        );
     }
-
     if args.print {
-        println!("Environment Variables:");
-        for (key, value) in &all_env_vars {
-            println!("{}={}", key, value);
+        deprecated(
+            "--print",
+            "print",
+            Commands::Print {
+                format: env_file::Format::Dotenv,
+            },
+        );
+    }
+    match args.shell.as_deref() {
+        Some("SHELL") => deprecated(
+            "--shell",
+            "shell",
+            Commands::Shell {
+                stack: false,
+                child_env: ChildEnvArgs::default(),
+            },
+        ),
+        Some(command) => {
+            let shell = shell::default_shell();
+            let flag = if cfg!(target_os = "windows") {
+                "/C"
+            } else {
+                "-c"
+            };
+            deprecated(
+                "--shell <command>",
+                "run --",
+                Commands::Run {
+                    command: vec![shell, flag.to_string(), command.to_string()],
+                    child_env: ChildEnvArgs::default(),
+                },
+            )
         }
+        None => {}
     }
 
-    if let Some(shell_value) = args.shell.as_deref() {
-        let shell_command = if shell_value == "SHELL" {
-            None
-        } else {
-            Some(shell_value)
-        };
-        let display_config = variables_file.as_deref().unwrap_or(&config_file);
-        spawn_interactive_shell(&all_env_vars, display_config, shell_command)?;
+    match &args.command {
+        Some(_) if !commands.is_empty() => Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "the deprecated flags --file, --print, --shell, --print-template and --clear-files can not be used with a subcommand",
+            )
+            .exit(),
+        Some(command) => vec![command.clone()],
+        None => commands,
     }
-
-    Ok(())
 }
 
-fn spawn_interactive_shell(
-    env_vars: &std::collections::BTreeMap<String, String>,
-    config_file: &str,
-    shell_command: Option<&str>,
-) -> Result<(), std::io::Error> {
-    // Check if we're already in a NAIS environment shell
-    if env::var("NAIS_ENV_ACTIVE").is_ok() {
-        println!("Already in a NAIS environment shell. Not spawning a new one.");
-        return Ok(());
+/// Loads the NAIS configuration from the cluster with `--app`, or from a local file
+///
+/// Without `--config`, the file is found in `.nais/`, see `discovery::find_candidates`.
+async fn load_config(
+    source: &mut SourceArgs,
+    context: &str,
+    context_policy: &policy::ContextPolicy,
+) -> Result<LoadedConfig, Error> {
+    if let Some(app) = &source.app {
+        let namespace = source.namespace.clone().unwrap_or_default();
+        let client = kubernetes_client::KubernetesClient::new(
+            namespace.clone(),
+            app.clone(),
+            kubernetes_client::Workload::Deployment,
+            context.to_string(),
+            context_policy,
+        )
+        .await?;
+
+        let config = client.get_application().await?;
+
+        return Ok(LoadedConfig {
+            config: nais::NaisConfigLoader::from_config(config),
+            processed_template: None,
+            display_config: format!("{}/{}", namespace, app),
+            client: Some(client),
+        });
     }
 
-    let shell = if cfg!(target_os = "windows") {
-        String::from("cmd")
-    } else {
-        env::var("SHELL").unwrap_or_else(|_| String::from("/bin/sh"))
+    let config_file = match &source.config {
+        Some(config_file) => config_file.clone(),
+        None => {
+            let candidate = discovery::select(discovery::find_candidates(), source.env.as_deref())?;
+            eprintln!("Using {}", candidate);
+            if source.variables.is_none() {
+                source.variables = candidate
+                    .variables
+                    .map(|path| path.to_string_lossy().to_string());
+            }
+            candidate.config.to_string_lossy().to_string()
+        }
     };
 
-    // Create command for the shell
-    let mut command = if let Some(cmd) = shell_command {
-        let cmd_args = if cfg!(target_os = "windows") {
-            vec!["/C", cmd]
-        } else {
-            vec!["-c", cmd]
-        };
-        let mut c = Command::new(&shell);
-        c.args(&cmd_args);
-        c
-    } else {
-        Command::new(shell)
+    let (config, processed_template) = match &source.variables {
+        Some(var_file) => {
+            let variables = yaml_vars::parse_variables_file(var_file)?;
+            let (config, processed) = nais::NaisConfigLoader::new_with_variables_and_template(
+                config_file.clone(),
+                variables,
+                source.select.as_deref(),
+            )?;
+            (config, Some(processed))
+        }
+        None => (
+            nais::NaisConfigLoader::new(config_file.clone(), source.select.as_deref())?,
+            None,
+        ),
     };
 
-    for (key, value) in env_vars {
-        command.env(key, value);
-    }
+    Ok(LoadedConfig {
+        config,
+        processed_template,
+        display_config: source.variables.clone().unwrap_or(config_file),
+        client: None,
+    })
+}
 
-    // Make it interactive by setting stdio options
-    command
-        .stdin(std::process::Stdio::inherit())
-        .stdout(std::process::Stdio::inherit())
-        .stderr(std::process::Stdio::inherit());
+/// The environment fetched from the cluster
+struct FetchedEnvironment {
+    /// Environment variables, without overrides
+    env_vars: std::collections::BTreeMap<String, String>,
+    /// Description of everything that could not be fetched
+    failures: Vec<String>,
+    /// Names of the secrets that variables or files were read from, in the order they were fetched
+    secrets: Vec<String>,
+}
 
-    let shell_path = env::var("SHELL").unwrap_or_else(|_| String::from("/bin/sh"));
+/// Fetches the environment of the workload from the cluster
+async fn fetch_environment(
+    source: &SourceArgs,
+    nais_config: &nais::NaisConfigLoader,
+    client: Option<kubernetes_client::KubernetesClient>,
+    context: String,
+    context_policy: &policy::ContextPolicy,
+) -> Result<FetchedEnvironment, Error> {
+    let cluster_name = kubernetes_client::cluster_name_from_context(&context);
+    let injected_env_vars = nais_config.get_injected_env_vars(&cluster_name);
 
-    // Set the environment variable to indicate that the shell is active
-    command.env("NAIS_ENV_ACTIVE", "true");
-    command.env("NAIS_ENV_CONFIG", config_file);
+    let kubernetes_client = match client {
+        Some(client) => client,
+        None => {
+            kubernetes_client::KubernetesClient::new(
+                nais_config.get_namespace(),
+                nais_config.get_deployment(),
+                nais_config.get_workload(),
+                context,
+                context_policy,
+            )
+            .await?
+        }
+    };
 
-    if shell_path.contains("bash") {
-        command.env(
-            "PS1",
-            "\\[\\e[32m\\][NAIS-ENV:$NAIS_ENV_CONFIG]\\[\\e[0m\\] \\w $ ",
-        );
+    // The workload is fetched once, and everything else is read from its pod template
+    let pod_spec = kubernetes_client.get_pod_spec().await?;
+
+    let resolved = kubernetes_client.resolve_env_from(&pod_spec).await;
+    let key_refs = kubernetes_client.resolve_env_key_refs(&pod_spec).await;
+    let mut failures = resolved.failures;
+    failures.extend(key_refs.failures);
+
+    let mut secrets = resolved.secrets;
+    let mut add_secrets = |names: Vec<String>| {
+        for name in names {
+            if !secrets.contains(&name) {
+                secrets.push(name);
+            }
+        }
+    };
+    add_secrets(key_refs.secrets);
+
+    let pod_info = if nais_config.has_field_refs() {
+        kubernetes_client.get_pod_info().await.unwrap_or_else(|e| {
+            eprintln!(
+                "Failed to find a running pod, using local placeholders: {}",
+                e
+            );
+            None
+        })
     } else {
-        // Fallback for other shells
-        command.env("PS1", "[NAIS-ENV:$NAIS_ENV_CONFIG] \\w $ ");
+        None
+    };
+
+    let nais_config_env_vars = nais_config.get_env_vars(pod_info.as_ref());
+
+    // Combine env_vars and secrets into a sorted map. Later sources take precedence:
+    // envFrom sources, naiserator-injected variables, valueFrom key references,
+    // env from nais.yaml and finally the overrides.
+    let base_env_vars: std::collections::BTreeMap<String, String> = resolved
+        .env_vars
+        .into_iter()
+        .chain(injected_env_vars)
+        .chain(key_refs.env_vars)
+        .collect();
+
+    // Expand $(VAR) references in env from nais.yaml the same way Kubernetes does
+    let mut all_env_vars = expansion::expand_env_vars(base_env_vars, nais_config_env_vars);
+
+    if let Some(files_root) = &source.files {
+        let volume_mounts = kubernetes_client::volume_mounts(&pod_spec);
+
+        // Mounts from nais.yaml take precedence over the ones found in the deployment
+        let config_files_from = nais_config.get_files_from();
+        let files_from: Vec<&nais::FilesFrom> = config_files_from
+            .iter()
+            .chain(volume_mounts.iter().filter(|mount| {
+                !config_files_from
+                    .iter()
+                    .any(|entry| entry.mountPath == mount.mountPath)
+            }))
+            .collect();
+
+        let (fetched_files, files_failures) =
+            files_from::fetch_files_from(&kubernetes_client, &files_from).await;
+        failures.extend(files_failures);
+        add_secrets(
+            fetched_files
+                .iter()
+                .filter_map(|entry| entry.secret.clone())
+                .collect(),
+        );
+
+        // With --strict nothing is written to disk unless the whole environment was fetched.
+        // The failures are reported by the caller.
+        if !source.strict || failures.is_empty() {
+            let mounts =
+                files_from::write_files_from(&fetched_files, std::path::Path::new(files_root))?;
+            files_from::rewrite_mount_paths(&mut all_env_vars, &mounts);
+        }
     }
 
-    // Execute the command
-    match command.spawn() {
-        Ok(mut child) => match child.wait() {
-            Ok(status) => {
-                if shell_command.is_some() {
-                    println!("Command exited with status: {}", status);
-                } else {
-                    println!("Shell exited with status: {}", status);
-                }
-                Ok(())
+    Ok(FetchedEnvironment {
+        env_vars: all_env_vars,
+        failures,
+        secrets,
+    })
+}
+
+/// Prints the differences between an env file and the fetched environment
+///
+/// Values are hidden unless `show_values` is set, since they are usually secrets.
+fn print_diff(
+    file: &str,
+    existing: &std::collections::BTreeMap<String, String>,
+    fetched: &std::collections::BTreeMap<String, String>,
+    show_values: bool,
+) {
+    let changes = env_file::diff_env_vars(existing, fetched);
+    if changes.is_empty() {
+        println!("{} is up to date", file);
+        return;
+    }
+
+    for change in changes {
+        match change {
+            env_file::EnvChange::Added { key, value } if show_values => {
+                println!("+ {}={}", key, value)
             }
-            Err(e) => {
-                eprintln!("Error waiting for process: {}", e);
-                Err(e)
+            env_file::EnvChange::Added { key, .. } => println!("+ {}", key),
+            env_file::EnvChange::Removed { key, value } if show_values => {
+                println!("- {}={}", key, value)
             }
-        },
-        Err(e) => {
-            if shell_command.is_some() {
-                eprintln!("Failed to execute command: {}", e);
-            } else {
-                eprintln!("Failed to launch shell: {}", e);
+            env_file::EnvChange::Removed { key, .. } => println!("- {}", key),
+            env_file::EnvChange::Changed { key, old, new } if show_values => {
+                println!("~ {}: {} -> {}", key, old, new)
             }
-            Err(e)
+            env_file::EnvChange::Changed { key, .. } => println!("~ {}", key),
         }
     }
 }
 
+/// Fills in source arguments that are not given on the command line from a profile
+///
+/// The Application given with `--app` replaces the config, variables and selector from the
+/// profile, and `--env` replaces the config.
+///
+/// # Returns
+///
+/// The values from the profile that are used by the subcommands
+fn apply_profile(source: &mut SourceArgs, profile: project::Profile) -> ProfileDefaults {
+    if source.app.is_none() {
+        // An environment from the command line replaces the config from the profile
+        if source.env.is_none() {
+            source.config = source.config.take().or(profile.config);
+        }
+        if source.config.is_none() {
+            source.env = source.env.take().or(profile.env);
+        }
+        source.variables = source.variables.take().or(profile.variables);
+        source.select = source.select.take().or(profile.select);
+    }
+    source.context = source.context.take().or(profile.context);
+    source.overrides = source.overrides.take().or(profile.overrides);
+    source.files = source.files.take().or(profile.files);
+
+    ProfileDefaults {
+        file: profile.file,
+        shell: profile.shell,
+        clean_env: profile.clean_env.unwrap_or(false),
+        keep_env: profile.keep_env.unwrap_or_default(),
+    }
+}
+
 /// Generate shell completion scripts
 fn generate_completion(shell: &CompletionShell) -> io::Result<()> {
     let mut cmd = Args::command();
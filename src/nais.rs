@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::error::ConfigError;
+use crate::kubernetes_client::{PodInfo, Workload};
+
 #[allow(non_snake_case)]
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NaisConfig {
@@ -271,53 +274,251 @@ pub struct Tokenx {
     pub enabled: Option<bool>,
 }
 
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Naisjob {
+    pub apiVersion: String,
+    pub kind: String,
+    pub metadata: Metadata,
+    pub spec: NaisjobSpec,
+}
+
+#[allow(non_snake_case)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NaisjobSpec {
+    #[serde(default)]
+    pub accessPolicy: Option<AccessPolicy>,
+    #[serde(default)]
+    pub activeDeadlineSeconds: Option<i64>,
+    #[serde(default)]
+    pub azure: Option<Azure>,
+    #[serde(default)]
+    pub backoffLimit: Option<i32>,
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    #[serde(default)]
+    pub completions: Option<i32>,
+    #[serde(default)]
+    pub concurrencyPolicy: Option<String>,
+    #[serde(default)]
+    pub env: Option<Vec<NaisEnvVar>>,
+    #[serde(default)]
+    pub envFrom: Option<Vec<EnvFrom>>,
+    #[serde(default)]
+    pub failedJobsHistoryLimit: Option<i32>,
+    #[serde(default)]
+    pub filesFrom: Option<Vec<FilesFrom>>,
+    pub image: String,
+    #[serde(default)]
+    pub maskinporten: Option<Maskinporten>,
+    #[serde(default)]
+    pub parallelism: Option<i32>,
+    /// Cron schedule. A Naisjob with a schedule runs as a CronJob, otherwise as a single Job.
+    #[serde(default)]
+    pub schedule: Option<String>,
+    #[serde(default)]
+    pub successfulJobsHistoryLimit: Option<i32>,
+    #[serde(default)]
+    pub timeZone: Option<String>,
+    #[serde(default)]
+    pub ttlSecondsAfterFinished: Option<i32>,
+}
+
+/// The NAIS resource kinds nais-env can read configuration from
+#[derive(Debug)]
+pub enum NaisResource {
+    Application(NaisConfig),
+    Naisjob(Naisjob),
+}
+
+impl NaisResource {
+    fn metadata(&self) -> &Metadata {
+        match self {
+            NaisResource::Application(config) => &config.metadata,
+            NaisResource::Naisjob(job) => &job.metadata,
+        }
+    }
+
+    fn env(&self) -> Option<&Vec<NaisEnvVar>> {
+        match self {
+            NaisResource::Application(config) => config.spec.env.as_ref(),
+            NaisResource::Naisjob(job) => job.spec.env.as_ref(),
+        }
+    }
+
+    fn files_from(&self) -> Option<&Vec<FilesFrom>> {
+        match self {
+            NaisResource::Application(config) => config.spec.filesFrom.as_ref(),
+            NaisResource::Naisjob(job) => job.spec.filesFrom.as_ref(),
+        }
+    }
+
+    fn image(&self) -> &str {
+        match self {
+            NaisResource::Application(config) => &config.spec.image,
+            NaisResource::Naisjob(job) => &job.spec.image,
+        }
+    }
+}
+
+/// The NAIS resource kinds that describe a workload nais-env can read configuration from
+const WORKLOAD_KINDS: [&str; 2] = ["Application", "Naisjob"];
+
+/// Checks whether a document is a NAIS workload, i.e. an Application or a Naisjob
+///
+/// # Arguments
+/// * `api_version` - The `apiVersion` of the document
+/// * `kind` - The `kind` of the document
+pub fn is_workload(api_version: &str, kind: &str) -> bool {
+    api_version.starts_with("nais.io/") && WORKLOAD_KINDS.contains(&kind)
+}
+
+/// Parses the configuration content into the resource type matching its kind
+///
+/// The content may contain several YAML documents separated by `---`, for example an
+/// Application bundled with its Topic and Alert resources. The workload document is chosen
+/// by its `apiVersion` and `kind`, and every other document is reported as skipped.
+///
+/// # Arguments
+/// * `path` - Path to the configuration file, used in errors
+/// * `content` - The configuration content
+/// * `template` - The original template, if the content was processed with variables
+/// * `selector` - Name of the workload to use when the content contains more than one
+///
+/// # Errors
+/// Returns a `ConfigError` if a document is not valid YAML, if no workload is found, if
+/// several are found without a matching selector, or if the workload does not match the
+/// expected structure.
+fn parse_resource(
+    path: &str,
+    content: &str,
+    template: Option<&str>,
+    selector: Option<&str>,
+) -> Result<NaisResource, ConfigError> {
+    let yaml_error = |e: serde_yaml::Error| ConfigError::from_yaml(path, &e, content, template);
+    let workload_error = |message: String| ConfigError::Workload {
+        path: path.to_string(),
+        message,
+    };
+
+    let mut workloads = Vec::new();
+
+    for (index, document) in serde_yaml::Deserializer::from_str(content).enumerate() {
+        let value = serde_yaml::Value::deserialize(document).map_err(yaml_error)?;
+        if value.is_null() {
+            continue;
+        }
+
+        let field = |name: &str| value.get(name).and_then(|v| v.as_str()).unwrap_or_default();
+        let api_version = field("apiVersion");
+        let kind = field("kind");
+        let name = value
+            .get("metadata")
+            .and_then(|metadata| metadata.get("name"))
+            .and_then(|name| name.as_str())
+            .unwrap_or_default();
+
+        if is_workload(api_version, kind) {
+            workloads.push((index, kind.to_string(), name.to_string()));
+        } else {
+            eprintln!(
+                "Skipping document {} ({} {} {})",
+                index + 1,
+                api_version,
+                kind,
+                name
+            );
+        }
+    }
+
+    let (index, kind, name) = match (workloads.len(), selector) {
+        (0, _) => {
+            return Err(workload_error(String::from(
+                "Expected a document with kind: Application or kind: Naisjob",
+            )));
+        }
+        (_, Some(selector)) => match workloads.iter().position(|(_, _, name)| name == selector) {
+            Some(position) => workloads.swap_remove(position),
+            None => {
+                return Err(workload_error(format!(
+                    "No Application or Naisjob named '{}'",
+                    selector
+                )));
+            }
+        },
+        (1, None) => workloads.remove(0),
+        (_, None) => {
+            let names: Vec<&str> = workloads.iter().map(|(_, _, name)| name.as_str()).collect();
+            return Err(workload_error(format!(
+                "Found several workloads ({}), use --select to choose one",
+                names.join(", ")
+            )));
+        }
+    };
+
+    for (other_index, other_kind, other_name) in &workloads {
+        if *other_index != index {
+            eprintln!(
+                "Skipping document {} ({} {})",
+                other_index + 1,
+                other_kind,
+                other_name
+            );
+        }
+    }
+
+    // Deserialize the chosen document directly, so errors point at the right place in the content
+    let document = serde_yaml::Deserializer::from_str(content)
+        .nth(index)
+        .ok_or_else(|| workload_error(format!("Could not read {} {}", kind, name)))?;
+
+    let resource = if kind == "Naisjob" {
+        NaisResource::Naisjob(Naisjob::deserialize(document).map_err(yaml_error)?)
+    } else {
+        NaisResource::Application(NaisConfig::deserialize(document).map_err(yaml_error)?)
+    };
+
+    Ok(resource)
+}
+
 pub struct NaisConfigLoader {
-    config: NaisConfig,
+    config: NaisResource,
 }
 
 impl NaisConfigLoader {
     /// Creates a new `NaisConfigLoader` instance from a configuration file.
     ///
     /// This function reads the NAIS configuration from the specified file path,
-    /// validates that it contains an "Application" kind, and parses it into a
+    /// picks the document with an "Application" or "Naisjob" kind, and parses it into a
     /// structured representation.
     ///
     /// # Arguments
     /// * `config_path` - The path to the NAIS configuration file
+    /// * `selector` - Name of the workload to use when the file contains several
     ///
     /// # Returns
     /// A `Result` containing either the constructed `NaisConfigLoader` or an error
     /// if the file cannot be read or parsed.
     ///
     /// # Errors
-    /// This function will return an error if:
+    /// This function will return a `ConfigError` if:
     /// * The configuration file cannot be read
-    /// * The configuration does not contain "kind: \"Application\""
+    /// * The configuration does not contain a document with kind Application or Naisjob
+    /// * The configuration contains several workloads and no matching selector
     /// * The YAML cannot be parsed into the expected structure
     ///
     /// # Example
     /// ```
-    /// let config_loader = NaisConfigLoader::new("nais.yaml".to_string())?;
+    /// let config_loader = NaisConfigLoader::new("nais.yaml".to_string(), None)?;
     /// ```
-    pub fn new(config_path: String) -> Result<Self, Box<dyn std::error::Error>> {
-        let content = match std::fs::read_to_string(&config_path) {
-            Ok(content) => content,
-            Err(e) => {
-                return Err(format!("Failed to read config file {}: {}", config_path, e).into());
-            }
-        };
+    pub fn new(config_path: String, selector: Option<&str>) -> Result<Self, ConfigError> {
+        let content =
+            std::fs::read_to_string(&config_path).map_err(|source| ConfigError::Read {
+                path: config_path.clone(),
+                source,
+            })?;
 
-        if !content.contains("kind: \"Application\"")
-            && !content.contains("kind: \'Application\'")
-            && !content.contains("kind: Application")
-        {
-            return Err("Expected kind: Application".into());
-        }
-
-        let config: NaisConfig = match serde_yaml::from_str(&content) {
-            Ok(config) => config,
-            Err(e) => panic!("Failed to parse config as YAML: {}", e),
-        };
+        let config = parse_resource(&config_path, &content, None, selector)?;
 
         Ok(NaisConfigLoader { config })
     }
@@ -332,48 +533,57 @@ impl NaisConfigLoader {
     /// # Arguments
     /// * `config_path` - The path to the NAIS configuration file
     /// * `variables` - A YAML structure containing variables for substitution
+    /// * `selector` - Name of the workload to use when the file contains several
     ///
     /// # Returns
     /// A `Result` containing either a tuple of the constructed `NaisConfigLoader` and the
     /// processed template content, or an error if the file cannot be read or parsed.
     ///
+    /// # Errors
+    /// In addition to the errors from `new`, this returns `ConfigError::Template` if the
+    /// template cannot be rendered. YAML errors include the matching line in the template.
+    ///
     /// # Example
     /// ```
     /// let variables = yaml_vars::parse_variables_file("vars.yaml")?;
     /// let (config_loader, processed_template) = NaisConfigLoader::new_with_variables_and_template(
     ///     "nais.yaml".to_string(),
-    ///     variables
+    ///     variables,
+    ///     None,
     /// )?;
     /// ```
     pub fn new_with_variables_and_template(
         config_path: String,
         variables: serde_yaml::Value,
-    ) -> Result<(Self, String), Box<dyn std::error::Error>> {
-        let content = match std::fs::read_to_string(&config_path) {
-            Ok(content) => content,
-            Err(e) => {
-                return Err(format!("Failed to read config file {}: {}", config_path, e).into());
-            }
-        };
+        selector: Option<&str>,
+    ) -> Result<(Self, String), ConfigError> {
+        let content =
+            std::fs::read_to_string(&config_path).map_err(|source| ConfigError::Read {
+                path: config_path.clone(),
+                source,
+            })?;
 
         // Substitute variables in the content using template syntax
-        let processed_content = crate::yaml_vars::substitute_variables(&content, &variables);
+        let processed_content = crate::yaml_vars::substitute_variables(&content, &variables)
+            .map_err(|e| ConfigError::from_template(&config_path, &e))?;
 
-        if !processed_content.contains("kind: \"Application\"")
-            && !processed_content.contains("kind: \'Application\'")
-            && !processed_content.contains("kind: Application")
-        {
-            return Err("Expected kind: Application".into());
-        }
-
-        let config: NaisConfig = match serde_yaml::from_str(&processed_content) {
-            Ok(config) => config,
-            Err(e) => panic!("Failed to parse config as YAML: {}", e),
-        };
+        let config = parse_resource(&config_path, &processed_content, Some(&content), selector)?;
 
         Ok((NaisConfigLoader { config }, processed_content))
     }
 
+    /// Creates a new `NaisConfigLoader` from an already parsed configuration.
+    ///
+    /// This is used when the Application is read from the cluster instead of a file.
+    ///
+    /// # Arguments
+    /// * `config` - The parsed NAIS configuration
+    pub fn from_config(config: NaisConfig) -> Self {
+        NaisConfigLoader {
+            config: NaisResource::Application(config),
+        }
+    }
+
     /// Retrieves the namespace from the NAIS configuration.
     ///
     /// This method returns the namespace specified in the metadata section
@@ -384,12 +594,12 @@ impl NaisConfigLoader {
     ///
     /// # Example
     /// ```
-    /// let config_loader = NaisConfigLoader::new("nais.yaml".to_string()).unwrap();
+    /// let config_loader = NaisConfigLoader::new("nais.yaml".to_string(), None).unwrap();
     /// let namespace = config_loader.get_namespace();
     /// println!("Namespace: {}", namespace);
     /// ```
     pub fn get_namespace(&self) -> String {
-        self.config.metadata.namespace.clone()
+        self.config.metadata().namespace.clone()
     }
 
     /// Retrieves the deployment name from the NAIS configuration.
@@ -402,33 +612,262 @@ impl NaisConfigLoader {
     ///
     /// # Example
     /// ```
-    /// let config_loader = NaisConfigLoader::new("nais.yaml".to_string()).unwrap();
+    /// let config_loader = NaisConfigLoader::new("nais.yaml".to_string(), None).unwrap();
     /// let deployment_name = config_loader.get_deployment();
     /// println!("Deployment name: {}", deployment_name);
     /// ```
     pub fn get_deployment(&self) -> String {
-        self.config.metadata.name.clone()
+        self.config.metadata().name.clone()
+    }
+
+    /// Retrieves the kind of Kubernetes workload naiserator creates for the configuration.
+    ///
+    /// # Returns
+    /// `Workload::Deployment` for an Application, `Workload::CronJob` for a Naisjob with a
+    /// schedule and `Workload::Job` for a Naisjob without one.
+    pub fn get_workload(&self) -> Workload {
+        match &self.config {
+            NaisResource::Application(_) => Workload::Deployment,
+            NaisResource::Naisjob(job) if job.spec.schedule.is_some() => Workload::CronJob,
+            NaisResource::Naisjob(_) => Workload::Job,
+        }
     }
 
     /// Retrieves all environment variables defined in the NAIS configuration file.
     ///
+    /// Entries using `valueFrom.fieldRef` are resolved the same way as the downward API does
+    /// in the cluster, see `resolve_field_ref`. Entries that can not be resolved are skipped
+    /// with a warning.
+    ///
+    /// # Arguments
+    /// * `pod` - A running pod of the application, used to resolve pod specific fields
+    ///
     /// # Returns
-    /// A sorted `BTreeMap` where keys are environment variable names and values are their corresponding values.
-    /// If an environment variable is defined without a value in the config, an empty string is used as default.
+    /// A vector of name and value pairs in the order they are declared, which matters for
+    /// `$(VAR)` expansion. If an environment variable is defined without a value in the config,
+    /// an empty string is used as default.
     ///
     /// # Example
     /// ```
-    /// let config_loader = NaisConfigLoader::new("nais.yaml".to_string()).unwrap();
-    /// let env_vars = config_loader.get_env_vars();
+    /// let config_loader = NaisConfigLoader::new("nais.yaml".to_string(), None).unwrap();
+    /// let env_vars = config_loader.get_env_vars(None);
     /// // Use the environment variables
     /// ```
-    pub fn get_env_vars(&self) -> std::collections::BTreeMap<String, String> {
-        let mut env_vars = std::collections::BTreeMap::new();
-        if let Some(env) = &self.config.spec.env {
+    pub fn get_env_vars(&self, pod: Option<&PodInfo>) -> Vec<(String, String)> {
+        let mut env_vars = Vec::new();
+        if let Some(env) = self.config.env() {
             for e in env {
-                env_vars.insert(e.name.clone(), e.value.clone().unwrap_or_default());
+                let field_ref = e.valueFrom.as_ref().and_then(|v| v.fieldRef.as_ref());
+                let value = match (&e.value, field_ref) {
+                    (None, Some(field_ref)) => {
+                        match self.resolve_field_ref(&field_ref.fieldPath, pod) {
+                            Some(value) => value,
+                            None => {
+                                eprintln!(
+                                    "Warning: Unsupported fieldPath '{}' for {}, skipping",
+                                    field_ref.fieldPath, e.name
+                                );
+                                continue;
+                            }
+                        }
+                    }
+                    (value, _) => value.clone().unwrap_or_default(),
+                };
+                env_vars.push((e.name.clone(), value));
             }
         }
         env_vars
     }
+
+    /// Returns true if any environment variable in the configuration uses `valueFrom.fieldRef`.
+    pub fn has_field_refs(&self) -> bool {
+        self.config.env().into_iter().flatten().any(|e| {
+            e.value.is_none() && e.valueFrom.as_ref().is_some_and(|v| v.fieldRef.is_some())
+        })
+    }
+
+    /// Resolves a downward API field path locally.
+    ///
+    /// Values are taken from the running pod when one is given, otherwise from the
+    /// configuration, falling back to local placeholders for fields that only exist
+    /// in the cluster:
+    ///
+    /// * `metadata.name` - the pod name, or `<app>-local`
+    /// * `metadata.namespace` - the namespace from the configuration
+    /// * `metadata.labels['<key>']` - a pod label, or a label from the configuration
+    /// * `spec.nodeName` - the node name, or `localhost`
+    /// * `status.podIP` - the pod IP, or `127.0.0.1`
+    ///
+    /// # Returns
+    /// The resolved value, or `None` if the field path is not supported.
+    fn resolve_field_ref(&self, field_path: &str, pod: Option<&PodInfo>) -> Option<String> {
+        let metadata = self.config.metadata();
+
+        if let Some(key) = field_path
+            .strip_prefix("metadata.labels[")
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            let key = key.trim_matches(|c| c == '\'' || c == '"');
+            let label = pod
+                .and_then(|pod| pod.labels.get(key).cloned())
+                .or_else(|| metadata.labels.get(key).cloned())
+                .or_else(|| (key == "app").then(|| metadata.name.clone()));
+            return Some(label.unwrap_or_default());
+        }
+
+        match field_path {
+            "metadata.name" => Some(
+                pod.map(|pod| pod.name.clone())
+                    .unwrap_or_else(|| format!("{}-local", metadata.name)),
+            ),
+            "metadata.namespace" => Some(metadata.namespace.clone()),
+            "spec.nodeName" => Some(
+                pod.and_then(|pod| pod.node_name.clone())
+                    .unwrap_or_else(|| String::from("localhost")),
+            ),
+            "status.podIP" => Some(
+                pod.and_then(|pod| pod.pod_ip.clone())
+                    .unwrap_or_else(|| String::from("127.0.0.1")),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Retrieves the `filesFrom` entries defined in the NAIS configuration file.
+    ///
+    /// # Returns
+    /// A slice with the entries, empty if the configuration has no `filesFrom` section.
+    pub fn get_files_from(&self) -> &[FilesFrom] {
+        self.config
+            .files_from()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Computes the environment variables that naiserator injects into every application.
+    ///
+    /// These are normally only available inside the cluster, but some applications depend
+    /// on them, so they are emulated locally from the configuration and the chosen cluster.
+    ///
+    /// # Arguments
+    /// * `cluster_name` - The name of the cluster the application runs in, e.g. `dev-gcp`
+    ///
+    /// # Returns
+    /// A sorted `BTreeMap` with the injected environment variables.
+    ///
+    /// # Example
+    /// ```
+    /// let config_loader = NaisConfigLoader::new("nais.yaml".to_string(), None).unwrap();
+    /// let injected = config_loader.get_injected_env_vars("dev-gcp");
+    /// assert_eq!(injected["NAIS_CLUSTER_NAME"], "dev-gcp");
+    /// ```
+    pub fn get_injected_env_vars(
+        &self,
+        cluster_name: &str,
+    ) -> std::collections::BTreeMap<String, String> {
+        let metadata = self.config.metadata();
+        let mut env_vars = std::collections::BTreeMap::new();
+
+        env_vars.insert("NAIS_APP_NAME".to_string(), metadata.name.clone());
+        env_vars.insert("NAIS_NAMESPACE".to_string(), metadata.namespace.clone());
+        env_vars.insert(
+            "NAIS_APP_IMAGE".to_string(),
+            self.config.image().to_string(),
+        );
+        env_vars.insert("NAIS_CLUSTER_NAME".to_string(), cluster_name.to_string());
+        env_vars.insert(
+            "NAIS_CLIENT_ID".to_string(),
+            format!("{}:{}:{}", cluster_name, metadata.namespace, metadata.name),
+        );
+        env_vars.insert(
+            "LOG4J_FORMAT_MSG_NO_LOOKUPS".to_string(),
+            "true".to_string(),
+        );
+
+        env_vars
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An Application `app` in `team` with an env entry for each field path
+    fn loader(field_paths: &[&str]) -> NaisConfigLoader {
+        let env: Vec<String> = field_paths
+            .iter()
+            .enumerate()
+            .map(|(index, field_path)| {
+                format!(
+                    "    - name: FIELD_{}\n      valueFrom:\n        fieldRef:\n          fieldPath: \"{}\"\n",
+                    index, field_path
+                )
+            })
+            .collect();
+        let content = format!(
+            "apiVersion: nais.io/v1alpha1\nkind: Application\nmetadata:\n  name: app\n  namespace: team\n  labels:\n    team: team\nspec:\n  image: ghcr.io/navikt/app:1\n  env:\n{}",
+            env.concat()
+        );
+        let config = parse_resource("nais.yaml", &content, None, None).unwrap();
+        NaisConfigLoader { config }
+    }
+
+    fn pod() -> PodInfo {
+        PodInfo {
+            name: String::from("app-5d8f9c-x2k4l"),
+            labels: [(String::from("team"), String::from("pod-team"))].into(),
+            node_name: Some(String::from("node-1")),
+            pod_ip: Some(String::from("10.0.0.7")),
+        }
+    }
+
+    #[test]
+    fn fields_without_a_pod_use_the_config_and_local_placeholders() {
+        let loader = loader(&[]);
+        let resolve = |field_path| loader.resolve_field_ref(field_path, None);
+
+        assert_eq!(resolve("metadata.name").as_deref(), Some("app-local"));
+        assert_eq!(resolve("metadata.namespace").as_deref(), Some("team"));
+        assert_eq!(resolve("spec.nodeName").as_deref(), Some("localhost"));
+        assert_eq!(resolve("status.podIP").as_deref(), Some("127.0.0.1"));
+        assert_eq!(resolve("metadata.labels['team']").as_deref(), Some("team"));
+        assert_eq!(resolve("metadata.labels['app']").as_deref(), Some("app"));
+        assert_eq!(resolve("metadata.labels['missing']").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn fields_are_read_from_a_running_pod() {
+        let loader = loader(&[]);
+        let pod = pod();
+        let resolve = |field_path| loader.resolve_field_ref(field_path, Some(&pod));
+
+        assert_eq!(
+            resolve("metadata.name").as_deref(),
+            Some("app-5d8f9c-x2k4l")
+        );
+        assert_eq!(resolve("metadata.namespace").as_deref(), Some("team"));
+        assert_eq!(resolve("spec.nodeName").as_deref(), Some("node-1"));
+        assert_eq!(resolve("status.podIP").as_deref(), Some("10.0.0.7"));
+        assert_eq!(
+            resolve("metadata.labels[\"team\"]").as_deref(),
+            Some("pod-team")
+        );
+    }
+
+    #[test]
+    fn unsupported_field_paths_are_skipped() {
+        let loader = loader(&["metadata.namespace", "status.hostIP", "metadata.uid"]);
+
+        assert_eq!(loader.resolve_field_ref("status.hostIP", None), None);
+        assert_eq!(
+            loader.get_env_vars(None),
+            vec![(String::from("FIELD_0"), String::from("team"))]
+        );
+    }
+
+    #[test]
+    fn field_refs_are_detected() {
+        assert!(loader(&["metadata.name"]).has_field_refs());
+        assert!(!loader(&[]).has_field_refs());
+    }
 }
@@ -0,0 +1,253 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::Error;
+use crate::git;
+
+/// Name of the policy file in the root of a repository
+pub const REPO_POLICY_FILE: &str = ".nais-env-policy.yaml";
+
+/// Contexts that are allowed when no policy file is found
+const DEFAULT_CONTEXTS: [&str; 2] = ["nais-dev", "dev-fss"];
+
+/// Which Kubernetes contexts nais-env may use, and what it may read from them
+///
+/// # Example
+///
+/// ```yaml
+/// contexts:
+///   nais-dev:
+///     servers:
+///       - https://10.0.0.1
+///     deniedSecrets:
+///       - google-sql-*
+///   dev-fss: {}
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    pub contexts: BTreeMap<String, ContextPolicy>,
+    /// The files the policy was loaded from, empty for the default policy
+    #[serde(skip)]
+    pub sources: Vec<PathBuf>,
+}
+
+/// Rules for a single Kubernetes context
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct ContextPolicy {
+    /// Server URLs the context must resolve to, any server is allowed when empty
+    pub servers: Vec<String>,
+    /// Names of secrets that are never fetched, `*` matches any characters
+    pub denied_secrets: Vec<String>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            contexts: DEFAULT_CONTEXTS
+                .iter()
+                .map(|context| (context.to_string(), ContextPolicy::default()))
+                .collect(),
+            sources: Vec::new(),
+        }
+    }
+}
+
+impl Policy {
+    /// Loads the policy that applies in the current directory
+    ///
+    /// The user policy (`$XDG_CONFIG_HOME/nais-env/policy.yaml`, or
+    /// `~/.config/nais-env/policy.yaml`) is used if it exists, and otherwise the built-in
+    /// policy, which allows `nais-dev` and `dev-fss`. The repository policy
+    /// (`.nais-env-policy.yaml` in the git root) can only narrow that policy, so a cloned
+    /// repository can never allow more than the user has allowed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Policy` if a policy file exists but cannot be read or parsed.
+    pub fn load() -> Result<Self, Error> {
+        let mut policy = match user_policy_path().filter(|path| path.is_file()) {
+            Some(path) => Self::from_file(&path)?,
+            None => Self::default(),
+        };
+
+        if let Some(path) = git::get_repo_root()
+            .map(|root| root.join(REPO_POLICY_FILE))
+            .filter(|path| path.is_file())
+        {
+            policy = policy.narrow(Self::from_file(&path)?);
+        }
+
+        Ok(policy)
+    }
+
+    /// Combines the policy with a stricter one, allowing only what both allow
+    ///
+    /// A context must be allowed by both, servers must be allowed by both, and secrets denied
+    /// by either are denied.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The policy that narrows this one
+    pub fn narrow(self, other: Policy) -> Policy {
+        let mut contexts = BTreeMap::new();
+
+        for (name, context) in self.contexts {
+            let Some(narrower) = other.contexts.get(&name) else {
+                continue;
+            };
+
+            let servers = if context.servers.is_empty() {
+                narrower.servers.clone()
+            } else if narrower.servers.is_empty() {
+                context.servers
+            } else {
+                let servers: Vec<String> = context
+                    .servers
+                    .into_iter()
+                    .filter(|server| {
+                        narrower.servers.iter().any(|other| {
+                            other.trim_end_matches('/') == server.trim_end_matches('/')
+                        })
+                    })
+                    .collect();
+                // No servers left means none are allowed, not that any server is
+                if servers.is_empty() {
+                    continue;
+                }
+                servers
+            };
+
+            let mut denied_secrets = context.denied_secrets;
+            for pattern in &narrower.denied_secrets {
+                if !denied_secrets.contains(pattern) {
+                    denied_secrets.push(pattern.clone());
+                }
+            }
+
+            contexts.insert(
+                name,
+                ContextPolicy {
+                    servers,
+                    denied_secrets,
+                },
+            );
+        }
+
+        Policy {
+            contexts,
+            sources: self.sources.into_iter().chain(other.sources).collect(),
+        }
+    }
+
+    /// Reads a policy from a YAML file
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Policy` if the file cannot be read or parsed.
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let policy_error = |message: String| {
+            Error::Policy(format!("Invalid policy {}: {}", path.display(), message))
+        };
+
+        let content = std::fs::read_to_string(path).map_err(|e| policy_error(e.to_string()))?;
+        let mut policy: Self =
+            serde_yaml::from_str(&content).map_err(|e| policy_error(e.to_string()))?;
+        policy.sources = vec![path.to_path_buf()];
+        Ok(policy)
+    }
+
+    /// Returns the rules for a context
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Policy` if the context is not allowed by the policy.
+    pub fn context(&self, context: &str) -> Result<&ContextPolicy, Error> {
+        self.contexts.get(context).ok_or_else(|| {
+            let allowed = self
+                .contexts
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ");
+            if self.sources.is_empty() {
+                Error::Policy(format!(
+                    "Context '{}' is not allowed. Must be one of: {}",
+                    context, allowed
+                ))
+            } else {
+                let sources = self
+                    .sources
+                    .iter()
+                    .map(|source| source.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" and ");
+                Error::Policy(format!(
+                    "Context '{}' is not allowed by {}. Must be one of: {}",
+                    context, sources, allowed
+                ))
+            }
+        })
+    }
+}
+
+impl ContextPolicy {
+    /// Checks that a context resolves to one of the allowed servers
+    ///
+    /// Trailing slashes are ignored when comparing.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - Name of the context, used in the error
+    /// * `server` - Server URL from the kubeconfig
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Policy` if servers are listed and none of them match.
+    pub fn check_server(&self, context: &str, server: &str) -> Result<(), Error> {
+        let server = server.trim_end_matches('/');
+        if self.servers.is_empty()
+            || self
+                .servers
+                .iter()
+                .any(|allowed| allowed.trim_end_matches('/') == server)
+        {
+            return Ok(());
+        }
+
+        Err(Error::Policy(format!(
+            "Context '{}' points at {}, but the policy only allows: {}",
+            context,
+            server,
+            self.servers.join(", ")
+        )))
+    }
+
+    /// Checks whether a secret is on the deny-list
+    pub fn is_secret_denied(&self, secret_name: &str) -> bool {
+        self.denied_secrets.iter().any(|pattern| {
+            let pattern = format!(
+                "^{}$",
+                pattern
+                    .split('*')
+                    .map(regex::escape)
+                    .collect::<Vec<_>>()
+                    .join(".*")
+            );
+            Regex::new(&pattern).is_ok_and(|regex| regex.is_match(secret_name))
+        })
+    }
+}
+
+/// Path of the user policy file, if a config directory can be found
+fn user_policy_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+    Some(config_dir.join("nais-env").join("policy.yaml"))
+}
@@ -0,0 +1,158 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::ConfigError;
+use crate::git;
+
+/// Name of the project configuration file in the root of a repository
+pub const PROJECT_FILE: &str = ".nais-env.yaml";
+
+/// Project configuration with named profiles
+///
+/// # Example
+///
+/// ```yaml
+/// default: dev
+/// profiles:
+///   dev:
+///     env: dev
+///     context: dev-fss
+///     file: .env
+///     overrides:
+///       - local.env
+///     shell: zsh
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Profile used when `--profile` is not given
+    pub default: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+    /// The file the configuration was loaded from
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// Default values for the command line arguments
+///
+/// Paths are relative to the directory of the project configuration file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct Profile {
+    /// Path to nais.yaml
+    pub config: Option<String>,
+    /// YAML file containing variables
+    pub variables: Option<String>,
+    /// Environment to use from `.nais/` when no config is given
+    pub env: Option<String>,
+    /// Name of the Application or Naisjob to use
+    pub select: Option<String>,
+    /// Kubernetes context to use
+    pub context: Option<String>,
+    /// File to save the environment variables to
+    pub file: Option<String>,
+    /// Files with environment variables that override the ones from Kubernetes
+    pub overrides: Option<Vec<String>>,
+    /// Shell to start with `nais-env shell` instead of `$SHELL`, e.g. `zsh`
+    pub shell: Option<String>,
+    /// Start shells and programs with only a minimal environment, see `--clean-env`
+    pub clean_env: Option<bool>,
+    /// Variables to keep with `cleanEnv`
+    pub keep_env: Option<Vec<String>>,
+    /// Directory to write files from filesFrom to
+    pub files: Option<String>,
+}
+
+impl ProjectConfig {
+    /// Finds the project configuration in the git root, or the current directory when not in
+    /// a git repository
+    ///
+    /// # Returns
+    ///
+    /// The project configuration, or `None` if there is no project configuration file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be read or parsed.
+    pub fn find() -> Result<Option<Self>, ConfigError> {
+        let root = git::get_repo_root().unwrap_or_else(|| PathBuf::from("."));
+        let path = root.join(PROJECT_FILE);
+
+        if path.is_file() {
+            Self::from_file(&path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Reads the project configuration from a YAML file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let display_path = path.display().to_string();
+        let content = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: display_path.clone(),
+            source,
+        })?;
+
+        let mut config: Self = serde_yaml::from_str(&content)
+            .map_err(|e| ConfigError::from_yaml(&display_path, &e, &content, None))?;
+        config.path = path.to_path_buf();
+        Ok(config)
+    }
+
+    /// Returns a profile with its paths resolved
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the profile, or `None` for the default profile
+    ///
+    /// # Returns
+    ///
+    /// The profile, or `None` if no name is given and there is no default profile
+    ///
+    /// # Errors
+    ///
+    /// Returns `ConfigError::Profile` if the profile does not exist.
+    pub fn profile(&self, name: Option<&str>) -> Result<Option<Profile>, ConfigError> {
+        let name = match name.or(self.default.as_deref()) {
+            Some(name) => name,
+            None if self.profiles.contains_key("default") => "default",
+            None => return Ok(None),
+        };
+
+        let profile = self
+            .profiles
+            .get(name)
+            .ok_or_else(|| ConfigError::Profile {
+                path: self.path.display().to_string(),
+                message: format!(
+                    "Profile '{}' not found. Available profiles: {}",
+                    name,
+                    self.profiles
+                        .keys()
+                        .map(String::as_str)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            })?;
+
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        let resolve = |path: &String| dir.join(path).to_string_lossy().to_string();
+
+        Ok(Some(Profile {
+            config: profile.config.as_ref().map(resolve),
+            variables: profile.variables.as_ref().map(resolve),
+            file: profile.file.as_ref().map(resolve),
+            overrides: profile
+                .overrides
+                .as_ref()
+                .map(|overrides| overrides.iter().map(resolve).collect()),
+            files: profile.files.as_ref().map(resolve),
+            ..profile.clone()
+        }))
+    }
+}
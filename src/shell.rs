@@ -0,0 +1,439 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use crate::error::Error;
+
+/// Variables kept from the user's environment with `--clean-env`
+pub const CLEAN_ENV_ALLOWLIST: [&str; 4] = ["PATH", "HOME", "TERM", "LANG"];
+
+/// Prompt prefix shown in shells started by nais-env
+const PROMPT_PREFIX: &str = "[NAIS-ENV:$NAIS_ENV_CONFIG]";
+
+/// What the environment in a nais-env shell was fetched from
+///
+/// Exported to the shell as `NAIS_ENV_*` variables, so prompts and scripts can show which app
+/// and cluster the environment belongs to.
+#[derive(Debug, Clone, Default)]
+pub struct ShellMetadata {
+    /// Config file, or `namespace/app` when read from the cluster
+    pub config: String,
+    /// Kubernetes context
+    pub context: String,
+    pub namespace: String,
+    /// Name of the Application or Naisjob
+    pub app: String,
+    /// When the environment was fetched, in RFC 3339 format
+    pub fetched_at: String,
+    /// Names of the secrets the variables and files were read from
+    pub secrets: Vec<String>,
+}
+
+impl ShellMetadata {
+    /// Returns the metadata as `NAIS_ENV_*` environment variables
+    ///
+    /// `NAIS_ENV_SECRETS` is a comma-separated list of secret names.
+    pub fn env_vars(&self) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            (String::from("NAIS_ENV_ACTIVE"), String::from("true")),
+            (String::from("NAIS_ENV_CONFIG"), self.config.clone()),
+            (String::from("NAIS_ENV_CONTEXT"), self.context.clone()),
+            (String::from("NAIS_ENV_NAMESPACE"), self.namespace.clone()),
+            (String::from("NAIS_ENV_APP"), self.app.clone()),
+            (String::from("NAIS_ENV_FETCHED_AT"), self.fetched_at.clone()),
+            (String::from("NAIS_ENV_SECRETS"), self.secrets.join(",")),
+        ])
+    }
+}
+
+/// Returns a short prompt segment, such as `team/my-app@dev-fss`, for the current nais-env
+/// shell
+///
+/// When environments are stacked the depth is added, e.g. `team/my-app@dev-fss [2]`.
+///
+/// # Returns
+///
+/// The segment, or `None` when not in a nais-env shell
+pub fn prompt_segment() -> Option<String> {
+    env::var_os("NAIS_ENV_ACTIVE")?;
+
+    let var = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+    let depth = current_depth();
+    let name = match (var("NAIS_ENV_NAMESPACE"), var("NAIS_ENV_APP")) {
+        (Some(namespace), Some(app)) => format!("{}/{}", namespace, app),
+        (None, Some(app)) => app,
+        _ => var("NAIS_ENV_CONFIG").unwrap_or_else(|| String::from("nais-env")),
+    };
+
+    let segment = match var("NAIS_ENV_CONTEXT") {
+        Some(context) => format!("{}@{}", name, context),
+        None => name,
+    };
+
+    Some(if depth > 1 {
+        format!("{} [{}]", segment, depth)
+    } else {
+        segment
+    })
+}
+
+/// Removes everything from the environment of a command except the allowlist and `keep`
+///
+/// Entries in `keep` ending with `*` keep every variable starting with the rest of the entry.
+fn clean_env(command: &mut Command, keep: &[String]) {
+    command.env_clear();
+    for (key, value) in env::vars_os() {
+        let key_str = key.to_string_lossy();
+        let kept = CLEAN_ENV_ALLOWLIST.contains(&key_str.as_ref())
+            || keep.iter().any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => key_str.starts_with(prefix),
+                None => *pattern == key_str,
+            });
+        if kept {
+            command.env(key, value);
+        }
+    }
+}
+
+/// Returns the keys nais-env injected into the current shell, from `NAIS_ENV_KEYS`
+fn injected_keys() -> Vec<String> {
+    env::var("NAIS_ENV_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|key| !key.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Returns how many environments are stacked in the current shell, 0 outside of nais-env
+fn current_depth() -> u32 {
+    if env::var_os("NAIS_ENV_ACTIVE").is_none() {
+        return 0;
+    }
+    env::var("NAIS_ENV_DEPTH")
+        .ok()
+        .and_then(|depth| depth.parse().ok())
+        .unwrap_or(1)
+}
+
+/// Shell that is being started, used to pick how the prompt is customized
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+    Other,
+}
+
+impl ShellKind {
+    fn from_path(shell: &str) -> Self {
+        let name = Path::new(shell)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        match name.as_str() {
+            "bash" => Self::Bash,
+            "zsh" => Self::Zsh,
+            "fish" => Self::Fish,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Returns the user's shell, `cmd` on Windows
+pub fn default_shell() -> String {
+    if cfg!(target_os = "windows") {
+        String::from("cmd")
+    } else {
+        env::var("SHELL").unwrap_or_else(|_| String::from("/bin/sh"))
+    }
+}
+
+/// Runs a program with the environment, passing the arguments as they are
+///
+/// With `clean_env`, the program only gets the fetched environment, the allowlist and the
+/// variables in `clean_env`.
+///
+/// On Unix nais-env is replaced by the program, so signals such as Ctrl-C go straight to it
+/// and its exit code becomes the exit code of nais-env.
+///
+/// # Errors
+///
+/// Returns an error if the program cannot be started.
+#[cfg(unix)]
+pub fn run_command(
+    env_vars: &BTreeMap<String, String>,
+    command: &[String],
+    clean_env: Option<&[String]>,
+) -> Result<i32, Error> {
+    use std::os::unix::process::CommandExt;
+
+    let Some((program, args)) = command.split_first() else {
+        return Err(Error::io(
+            "No program to run",
+            io::Error::from(io::ErrorKind::InvalidInput),
+        ));
+    };
+    let mut child = Command::new(program);
+    if let Some(keep) = clean_env {
+        self::clean_env(&mut child, keep);
+    }
+    let error = child.args(args).envs(env_vars).exec();
+
+    Err(Error::io(format!("Failed to run {}", program), error))
+}
+
+/// Runs a program with the environment, passing the arguments as they are
+///
+/// # Returns
+///
+/// The exit code of the program
+///
+/// # Errors
+///
+/// Returns an error if the program cannot be started.
+#[cfg(not(unix))]
+pub fn run_command(
+    env_vars: &BTreeMap<String, String>,
+    command: &[String],
+    clean_env: Option<&[String]>,
+) -> Result<i32, Error> {
+    let Some((program, args)) = command.split_first() else {
+        return Err(Error::io(
+            "No program to run",
+            io::Error::from(io::ErrorKind::InvalidInput),
+        ));
+    };
+    let mut child = Command::new(program);
+    if let Some(keep) = clean_env {
+        self::clean_env(&mut child, keep);
+    }
+    let status = child
+        .args(args)
+        .envs(env_vars)
+        .status()
+        .map_err(|e| Error::io(format!("Failed to run {}", program), e))?;
+
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Starts the user's shell with the environment and waits for it to exit
+///
+/// The shell is `shell` if given, e.g. from the profile, and otherwise `$SHELL`.
+///
+/// The prompt is prefixed with `[NAIS-ENV:<config>]`. bash gets a `PS1`, zsh gets a
+/// `ZDOTDIR` shim that sources the user's own startup files before prefixing `PROMPT`, and
+/// fish gets a `fish_prompt` that wraps the user's prompt.
+///
+/// The keys that are set are recorded in `NAIS_ENV_KEYS`, and the number of stacked
+/// environments in `NAIS_ENV_DEPTH`. Inside a nais-env shell, the keys of the current
+/// environment are removed before the new ones are set, so nothing from the old app or cluster
+/// is left behind. With `stack` they are kept, and the new environment is added on top.
+///
+/// # Arguments
+///
+/// * `env_vars` - Environment variables to set in the shell
+/// * `metadata` - What the environment was fetched from, exported as `NAIS_ENV_*` variables
+/// * `shell` - Shell to start instead of `$SHELL`, e.g. `zsh` or `/usr/bin/fish`
+/// * `stack` - Keep the environment of the current nais-env shell instead of replacing it
+/// * `clean_env` - Variables to keep from the user's environment in addition to the allowlist,
+///   or `None` to keep all of them
+///
+/// # Returns
+///
+/// The exit code of the shell, or 128 plus the signal number if it was killed by a signal
+///
+/// # Errors
+///
+/// Returns an error if the shell cannot be started.
+pub fn spawn_interactive_shell(
+    env_vars: &BTreeMap<String, String>,
+    metadata: &ShellMetadata,
+    shell: Option<&str>,
+    stack: bool,
+    clean_env: Option<&[String]>,
+) -> io::Result<i32> {
+    // A clean environment has nothing from the current nais-env shell to replace or stack on
+    let (previous_depth, previous_keys) = match clean_env {
+        Some(_) => (0, Vec::new()),
+        None => (current_depth(), injected_keys()),
+    };
+
+    let shell = shell.map(String::from).unwrap_or_else(default_shell);
+    let kind = ShellKind::from_path(&shell);
+
+    let mut command = Command::new(&shell);
+    if let Some(keep) = clean_env {
+        self::clean_env(&mut command, keep);
+    }
+
+    let (depth, mut keys) = if stack {
+        (previous_depth + 1, previous_keys)
+    } else {
+        for key in &previous_keys {
+            command.env_remove(key);
+        }
+        (1, Vec::new())
+    };
+    if previous_depth > 0 {
+        let current = env::var("NAIS_ENV_CONFIG").unwrap_or_default();
+        if stack {
+            eprintln!("Adding {} on top of {}", metadata.config, current);
+        } else {
+            eprintln!("Replacing {} with {}", current, metadata.config);
+        }
+    }
+    for key in env_vars.keys() {
+        if !keys.contains(key) {
+            keys.push(key.clone());
+        }
+    }
+
+    command
+        .envs(env_vars)
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit());
+
+    // Tell prompts and nested invocations that the shell is active, and what it is for
+    command.envs(metadata.env_vars());
+    command.env("NAIS_ENV_DEPTH", depth.to_string());
+    command.env("NAIS_ENV_KEYS", keys.join(","));
+
+    let mut zsh_shim = None;
+    match kind {
+        ShellKind::Bash => {
+            command.env(
+                "PS1",
+                format!("\\[\\e[32m\\]{}\\[\\e[0m\\] \\w $ ", PROMPT_PREFIX),
+            );
+        }
+        ShellKind::Zsh => {
+            let dir = create_zsh_shim()?;
+            let user_zdotdir = env::var_os("ZDOTDIR")
+                .or_else(|| env::var_os("HOME"))
+                .unwrap_or_default();
+            command.env("NAIS_ENV_USER_ZDOTDIR", user_zdotdir);
+            command.env("ZDOTDIR", &dir);
+            zsh_shim = Some(dir);
+        }
+        ShellKind::Fish => {
+            command.args(["-C", FISH_PROMPT]);
+        }
+        ShellKind::Other => {
+            command.env("PS1", format!("{} \\w $ ", PROMPT_PREFIX));
+        }
+    }
+
+    let status = command.status();
+
+    if let Some(dir) = zsh_shim {
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    match status {
+        Ok(status) => Ok(exit_code(status)),
+        Err(e) => {
+            eprintln!("Failed to launch shell {}: {}", shell, e);
+            Err(e)
+        }
+    }
+}
+
+/// Exit code of a process, following the shell convention of 128 plus the signal number
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+/// Wraps the user's `fish_prompt` so the prefix is shown in front of it
+const FISH_PROMPT: &str = r#"functions --copy fish_prompt __nais_env_original_fish_prompt
+function fish_prompt
+    set_color green
+    echo -n "[NAIS-ENV:$NAIS_ENV_CONFIG] "
+    set_color normal
+    __nais_env_original_fish_prompt
+end"#;
+
+/// Startup files zsh reads from `ZDOTDIR`, with the script each shim runs after sourcing the
+/// user's own file
+///
+/// zsh reads the files in this order, and `ZDOTDIR` has to point back at the shim between them.
+/// The user's file is sourced with `ZDOTDIR` set to the user's directory, so startup files that
+/// use `$ZDOTDIR` keep working. `.zshrc` is the last file for non-login shells, so it leaves
+/// `ZDOTDIR` as the user had it.
+const ZSH_STARTUP_FILES: [(&str, &str); 4] = [
+    (".zshenv", ""),
+    (".zprofile", ""),
+    (
+        ".zshrc",
+        "PROMPT=\"%F{green}[NAIS-ENV:$NAIS_ENV_CONFIG]%f $PROMPT\"\n\
+         [[ -o login ]] || __nais_env_restore_zdotdir\n",
+    ),
+    (".zlogin", "__nais_env_restore_zdotdir\n"),
+];
+
+/// Creates a new private directory in the temporary directory
+///
+/// The name is random and the directory must not exist already, like `mkdtemp`, so another
+/// user on a shared `/tmp` can not create it in advance and control what the shell sources.
+fn create_private_temp_dir(prefix: &str) -> io::Result<PathBuf> {
+    use std::hash::{BuildHasher, RandomState};
+
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+
+    let state = RandomState::new();
+    for attempt in 0u32..100 {
+        let random = state.hash_one((std::process::id(), attempt));
+        let dir = env::temp_dir().join(format!("{}-{:016x}", prefix, random));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        "Failed to create a unique temporary directory",
+    ))
+}
+
+/// Writes the `ZDOTDIR` shim for zsh to a new private temporary directory
+fn create_zsh_shim() -> io::Result<PathBuf> {
+    let dir = create_private_temp_dir("nais-env-zsh")?;
+
+    for (file, after) in ZSH_STARTUP_FILES {
+        let script = format!(
+            r#"__nais_env_shim="$ZDOTDIR"
+__nais_env_restore_zdotdir() {{
+    if [[ -n "$NAIS_ENV_USER_ZDOTDIR" && "$NAIS_ENV_USER_ZDOTDIR" != "$HOME" ]]; then
+        export ZDOTDIR="$NAIS_ENV_USER_ZDOTDIR"
+    else
+        unset ZDOTDIR
+    fi
+}}
+ZDOTDIR="$NAIS_ENV_USER_ZDOTDIR"
+[[ -f "$ZDOTDIR/{file}" ]] && source "$ZDOTDIR/{file}"
+ZDOTDIR="$__nais_env_shim"
+{after}"#
+        );
+        std::fs::write(dir.join(file), script)?;
+    }
+
+    Ok(dir)
+}
@@ -1,9 +1,11 @@
-use handlebars::Handlebars;
+use handlebars::{Handlebars, RenderError};
 use serde_yaml::Value;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 
+use crate::error::ConfigError;
+
 /// Parses a YAML file containing variables and returns the parsed YAML value.
 ///
 /// The YAML file should contain variables that can be used for substitution
@@ -16,7 +18,7 @@ use std::path::Path;
 /// A result containing the parsed YAML structure or an error if parsing fails
 ///
 /// # Errors
-/// This function will return an error if:
+/// This function will return `ConfigError::Variables` if:
 /// * The file cannot be read
 /// * The YAML cannot be parsed
 ///
@@ -24,16 +26,22 @@ use std::path::Path;
 /// ```
 /// let variables = parse_variables_file("vars.yaml")?;
 /// ```
-pub fn parse_variables_file<P: AsRef<Path>>(
-    file_path: P,
-) -> Result<Value, Box<dyn std::error::Error>> {
+pub fn parse_variables_file<P: AsRef<Path>>(file_path: P) -> Result<Value, ConfigError> {
+    let path = file_path.as_ref().display().to_string();
+    let variables_error = |message: String| ConfigError::Variables {
+        path: path.clone(),
+        message,
+    };
+
     // Read the file contents
-    let mut file = File::open(file_path.as_ref())?;
     let mut content = String::new();
-    file.read_to_string(&mut content)?;
+    File::open(file_path.as_ref())
+        .and_then(|mut file| file.read_to_string(&mut content))
+        .map_err(|e| variables_error(e.to_string()))?;
 
     // Parse YAML content as a Value
-    let variables: Value = serde_yaml::from_str(&content)?;
+    let variables: Value =
+        serde_yaml::from_str(&content).map_err(|e| variables_error(e.to_string()))?;
 
     Ok(variables)
 }
@@ -53,38 +61,38 @@ pub fn parse_variables_file<P: AsRef<Path>>(
 /// # Returns
 /// A new string with all variables substituted
 ///
+/// # Errors
+/// Returns a `RenderError` if the template is invalid or cannot be rendered. Errors
+/// in the template syntax keep the line and column where they occurred.
+///
 /// # Example
 /// ```
 /// let content = "name: {{ app.name }}";
 /// let variables = parse_variables_file("vars.yaml")?;
-/// let result = substitute_variables(content, &variables);
+/// let result = substitute_variables(content, &variables)?;
 /// ```
-pub fn substitute_variables(content: &str, variables: &Value) -> String {
+pub fn substitute_variables(content: &str, variables: &Value) -> Result<String, RenderError> {
     let mut handlebars = Handlebars::new();
 
     // Disable HTML escaping since we're working with YAML, not HTML
     handlebars.register_escape_fn(handlebars::no_escape);
 
     // Register the template
-    match handlebars.register_template_string("template", content) {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!("Error registering template: {}", e);
-            return content.to_string();
+    if let Err(e) = handlebars.register_template_string("template", content) {
+        let position = e.pos();
+        let mut error = RenderError::from(e);
+        if let Some((line, column)) = position {
+            error.line_no = Some(line);
+            error.column_no = Some(column);
         }
+        return Err(error);
     }
 
     // Convert YAML Value to serde_json::Value for Handlebars compatibility
     let json_value = yaml_to_json(variables);
 
     // Render the template
-    match handlebars.render("template", &json_value) {
-        Ok(result) => result,
-        Err(e) => {
-            eprintln!("Error rendering template: {}", e);
-            content.to_string()
-        }
-    }
+    handlebars.render("template", &json_value)
 }
 
 /// Converts a serde_yaml::Value to serde_json::Value
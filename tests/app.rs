@@ -0,0 +1,69 @@
+//! With `--app` the Application is read from the cluster instead of a local nais.yaml
+
+mod common;
+
+use common::{FakeApiServer, Fixture, TestDir, nais_env};
+use serde_json::json;
+
+/// The Application `app` in `team` as the API server returns it, with a status and
+/// server-set metadata that are not part of nais.yaml
+fn application() -> serde_json::Value {
+    json!({
+        "apiVersion": "nais.io/v1alpha1",
+        "kind": "Application",
+        "metadata": {
+            "name": "app",
+            "namespace": "team",
+            "labels": { "team": "team" },
+            "uid": "6f1c2a3e-0000-0000-0000-000000000000",
+            "resourceVersion": "42",
+            "generation": 3,
+        },
+        "spec": {
+            "image": "ghcr.io/navikt/app:2",
+            "env": [{ "name": "FROM_APPLICATION", "value": "cluster" }],
+            "envFrom": [{ "secret": "app" }],
+        },
+        "status": { "synchronizationState": "RolloutComplete" },
+    })
+}
+
+/// A cluster with the Deployment `app` and, unless `missing`, the Application `app`
+fn setup(missing: bool) -> (FakeApiServer, TestDir) {
+    let fixture = Fixture::new("app").env_from_secret("app", &[("API_KEY", "key")]);
+    let fixture = if missing {
+        fixture
+    } else {
+        fixture.resource(
+            "/apis/nais.io/v1alpha1/namespaces/team/applications/app",
+            application(),
+        )
+    };
+    fixture.start()
+}
+
+#[test]
+fn application_from_the_cluster_is_used() {
+    let (_server, dir) = setup(false);
+    let output = nais_env(&dir, &["--app", "app", "-n", "team", "print"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("FROM_APPLICATION=cluster\n"), "{}", stdout);
+    assert!(stdout.contains("API_KEY=key\n"), "{}", stdout);
+    assert!(
+        stdout.contains("NAIS_APP_IMAGE=ghcr.io/navikt/app:2\n"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn missing_application_is_not_found() {
+    let (_server, dir) = setup(true);
+    let output = nais_env(&dir, &["--app", "app", "-n", "team", "print"]);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(21), "{}", stderr);
+    assert!(stderr.contains("application 'app'"), "{}", stderr);
+}
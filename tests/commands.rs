@@ -0,0 +1,199 @@
+//! Each workflow is a subcommand, and the old flags still work as deprecated aliases
+
+mod common;
+
+use common::{FakeApiServer, Fixture, TestDir, nais_env};
+
+fn setup() -> (FakeApiServer, TestDir) {
+    Fixture::new("commands")
+        .env_from_secret("app", &[("API_KEY", "key"), ("TOKEN", "new-token")])
+        .start()
+}
+
+#[test]
+fn fetch_writes_env_file() {
+    let (_server, dir) = setup();
+    let output = nais_env(&dir, &["fetch", "--config", "nais.yaml"]);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    let env = std::fs::read_to_string(dir.path().join(".env")).unwrap();
+    assert!(env.contains("API_KEY=key"), "{}", env);
+}
+
+#[test]
+fn source_arguments_work_before_and_after_the_subcommand() {
+    let (_server, dir) = setup();
+
+    for args in [
+        ["--config", "nais.yaml", "print"],
+        ["print", "--config", "nais.yaml"],
+    ] {
+        let output = nais_env(&dir, &args);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+        assert!(stdout.contains("API_KEY=key"), "{}", stdout);
+    }
+}
+
+#[test]
+fn diff_shows_changed_keys_without_values() {
+    let (_server, dir) = setup();
+    dir.write(".env", "TOKEN=old-token\nREMOVED=value\n");
+    let output = nais_env(&dir, &["diff", "--config", "nais.yaml"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("+ API_KEY\n"), "{}", stdout);
+    assert!(stdout.contains("- REMOVED\n"), "{}", stdout);
+    assert!(stdout.contains("~ TOKEN\n"), "{}", stdout);
+    assert!(!stdout.contains("new-token"), "{}", stdout);
+
+    let output = nais_env(&dir, &["diff", "--config", "nais.yaml", "--show-values"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("~ TOKEN: old-token -> new-token"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn template_does_not_connect_to_kubernetes() {
+    let dir = TestDir::new("commands");
+    dir.write(
+        "nais.yaml",
+        "apiVersion: nais.io/v1alpha1\nkind: Application\nmetadata:\n  name: app\n  namespace: team\nspec:\n  image: {{ image }}\n",
+    );
+    dir.write("vars.yaml", "image: my-image\n");
+    let output = nais_env(
+        &dir,
+        &[
+            "template",
+            "--config",
+            "nais.yaml",
+            "--variables",
+            "vars.yaml",
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("image: my-image"), "{}", stdout);
+}
+
+#[test]
+fn deprecated_flags_still_work_with_a_warning() {
+    let (_server, dir) = setup();
+    let output = nais_env(&dir, &["--config", "nais.yaml", "--print"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stdout.contains("API_KEY=key"), "{}", stdout);
+    assert!(stderr.contains("--print is deprecated"), "{}", stderr);
+}
+
+#[test]
+fn deprecated_flags_can_not_be_combined_with_a_subcommand() {
+    let (_server, dir) = setup();
+    let output = nais_env(&dir, &["--print", "fetch", "--config", "nais.yaml"]);
+
+    assert_eq!(output.status.code(), Some(2), "{}", common::stderr(&output));
+}
+
+#[test]
+fn run_passes_arguments_and_environment_to_the_program() {
+    let (_server, dir) = setup();
+    let output = nais_env(
+        &dir,
+        &[
+            "run",
+            "--config",
+            "nais.yaml",
+            "--",
+            "sh",
+            "-c",
+            "printf '%s|%s' \"$API_KEY\" \"$1\"",
+            "sh",
+            "two words; not split",
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.ends_with("key|two words; not split"), "{}", stdout);
+}
+
+#[test]
+fn run_exits_with_the_exit_code_of_the_program() {
+    let (_server, dir) = setup();
+    let output = nais_env(
+        &dir,
+        &["run", "--config", "nais.yaml", "--", "sh", "-c", "exit 42"],
+    );
+
+    assert_eq!(
+        output.status.code(),
+        Some(42),
+        "{}",
+        common::stderr(&output)
+    );
+}
+
+#[test]
+fn deprecated_shell_command_exits_with_the_exit_code_of_the_command() {
+    let (_server, dir) = setup();
+    let output = nais_env(&dir, &["--config", "nais.yaml", "--shell", "exit 3"]);
+
+    assert_eq!(output.status.code(), Some(3), "{}", common::stderr(&output));
+}
+
+#[test]
+fn run_with_clean_env_only_keeps_the_allowlist_and_the_fetched_environment() {
+    let (_server, dir) = setup();
+    let script = "printf '%s|%s|%s' \"$API_KEY\" \"$HOME\" \"${CARGO_MANIFEST_DIR-unset}\"";
+
+    let output = nais_env(
+        &dir,
+        &[
+            "run",
+            "--config",
+            "nais.yaml",
+            "--clean-env",
+            "--",
+            "sh",
+            "-c",
+            script,
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(
+        stdout.ends_with(&format!("key|{}|unset", dir.path().display())),
+        "{}",
+        stdout
+    );
+
+    let output = nais_env(
+        &dir,
+        &[
+            "run",
+            "--config",
+            "nais.yaml",
+            "--clean-env",
+            "--keep-env",
+            "CARGO_*",
+            "--",
+            "sh",
+            "-c",
+            script,
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(
+        stdout.ends_with(&format!("|{}", env!("CARGO_MANIFEST_DIR"))),
+        "{}",
+        stdout
+    );
+}
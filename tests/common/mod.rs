@@ -0,0 +1,473 @@
+//! Shared helpers for the integration tests
+//!
+//! The tests run the `nais-env` binary against a fake Kubernetes API server on localhost,
+//! with a kubeconfig written to a temporary directory.
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde_json::{Value, json};
+
+/// A temporary directory that is removed when dropped
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Creates an empty directory below the system temp directory
+    pub fn new(name: &str) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "nais-env-{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("Failed to create test directory");
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes a file in the directory and returns its path
+    pub fn write(&self, name: &str, content: &str) -> PathBuf {
+        let path = self.path.join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).expect("Failed to create test directory");
+        }
+        std::fs::write(&path, content).expect("Failed to write test file");
+        path
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A response from the fake API server
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    /// A successful response with a JSON body
+    pub fn ok(body: Value) -> Self {
+        Self {
+            status: 200,
+            body: body.to_string(),
+        }
+    }
+
+    /// An error response with a Kubernetes `Status` body, like the real API server sends
+    pub fn status(code: u16, reason: &str, message: &str) -> Self {
+        Self {
+            status: code,
+            body: json!({
+                "kind": "Status",
+                "apiVersion": "v1",
+                "metadata": {},
+                "status": "Failure",
+                "message": message,
+                "reason": reason,
+                "code": code,
+            })
+            .to_string(),
+        }
+    }
+}
+
+type Handler = dyn Fn(&str) -> Response + Send + Sync;
+
+/// A minimal HTTP server that answers Kubernetes API requests from a handler
+///
+/// The handler gets the request path, including the query string. Every request line is
+/// recorded and can be inspected with `requests`.
+pub struct FakeApiServer {
+    url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl FakeApiServer {
+    pub fn start(handler: impl Fn(&str) -> Response + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind fake API server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let recorded = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let handler = Arc::clone(&handler);
+                let recorded = Arc::clone(&recorded);
+                thread::spawn(move || handle_connection(stream, &*handler, &recorded));
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    /// Base URL of the server, e.g. `http://127.0.0.1:12345`
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Request lines received so far, e.g. `GET /api/v1/namespaces/team/secrets/app`
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn handle_connection(stream: TcpStream, handler: &Handler, requests: &Mutex<Vec<String>>) {
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+        return;
+    }
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).unwrap_or(0) == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    let mut body = vec![0; content_length];
+    let _ = reader.read_exact(&mut body);
+
+    let request_line = request_line.trim().to_string();
+    requests.lock().unwrap().push(request_line.clone());
+
+    let path = request_line.split(' ').nth(1).unwrap_or("/");
+    let response = handler(path);
+    let mut stream = &stream;
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {} Fake\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.body.len(),
+        response.body
+    );
+}
+
+/// A kubeconfig user that authenticates with a static token
+pub fn token_user() -> Value {
+    json!({ "token": "test-token" })
+}
+
+/// A kubeconfig user that gets its credentials from an exec plugin
+pub fn exec_user(command: &str) -> Value {
+    json!({
+        "exec": {
+            "apiVersion": "client.authentication.k8s.io/v1beta1",
+            "command": command,
+            "args": [],
+        }
+    })
+}
+
+/// Builds a kubeconfig with a single context, written as JSON since that is valid YAML
+pub fn kubeconfig(context: &str, server: &str, user: Value) -> String {
+    json!({
+        "apiVersion": "v1",
+        "kind": "Config",
+        "current-context": context,
+        "clusters": [{ "name": "test", "cluster": { "server": server } }],
+        "users": [{ "name": "test", "user": user }],
+        "contexts": [{
+            "name": context,
+            "context": { "cluster": "test", "user": "test" },
+        }],
+    })
+    .to_string()
+}
+
+/// A minimal nais.yaml for an Application
+pub fn nais_yaml(name: &str, namespace: &str) -> String {
+    json!({
+        "apiVersion": "nais.io/v1alpha1",
+        "kind": "Application",
+        "metadata": { "name": name, "namespace": namespace, "labels": { "team": namespace } },
+        "spec": { "image": "ghcr.io/navikt/app:1" },
+    })
+    .to_string()
+}
+
+/// A pod template with a single container, labeled the way naiserator does it
+fn pod_template(name: &str, container: Value) -> Value {
+    let mut container = container;
+    container["name"] = json!(name);
+    json!({
+        "metadata": { "labels": { "app": name } },
+        "spec": { "containers": [container] },
+    })
+}
+
+/// A Deployment with a single container, as returned by the API server
+pub fn deployment(name: &str, namespace: &str, container: Value) -> Value {
+    json!({
+        "apiVersion": "apps/v1",
+        "kind": "Deployment",
+        "metadata": { "name": name, "namespace": namespace },
+        "spec": {
+            "selector": { "matchLabels": { "app": name } },
+            "template": pod_template(name, container),
+        },
+    })
+}
+
+/// A CronJob with a single container, as returned by the API server
+pub fn cronjob(name: &str, namespace: &str, container: Value) -> Value {
+    json!({
+        "apiVersion": "batch/v1",
+        "kind": "CronJob",
+        "metadata": { "name": name, "namespace": namespace },
+        "spec": {
+            "schedule": "0 * * * *",
+            "jobTemplate": { "spec": { "template": pod_template(name, container) } },
+        },
+    })
+}
+
+/// A Job with a single container, as returned by the API server
+pub fn job(name: &str, namespace: &str, container: Value) -> Value {
+    json!({
+        "apiVersion": "batch/v1",
+        "kind": "Job",
+        "metadata": { "name": name, "namespace": namespace },
+        "spec": { "template": pod_template(name, container) },
+    })
+}
+
+/// The kind of workload a `Fixture` serves the pod template in
+#[derive(Debug, Clone, Copy)]
+pub enum Workload {
+    Deployment,
+    CronJob,
+    Job,
+}
+
+impl Workload {
+    /// The workload `app` in `team` with the container, and the API path it is served at
+    fn resource(self, container: Value, volumes: Option<Value>) -> (String, Value) {
+        let (path, mut body, pod_spec) = match self {
+            Workload::Deployment => (
+                "/apis/apps/v1/namespaces/team/deployments/app",
+                deployment("app", "team", container),
+                "/spec/template/spec",
+            ),
+            Workload::CronJob => (
+                "/apis/batch/v1/namespaces/team/cronjobs/app",
+                cronjob("app", "team", container),
+                "/spec/jobTemplate/spec/template/spec",
+            ),
+            Workload::Job => (
+                "/apis/batch/v1/namespaces/team/jobs/app",
+                job("app", "team", container),
+                "/spec/template/spec",
+            ),
+        };
+        if let Some(volumes) = volumes {
+            body.pointer_mut(pod_spec).unwrap()["volumes"] = volumes;
+        }
+        (path.to_string(), body)
+    }
+}
+
+/// A Secret with string values, as returned by the API server
+pub fn secret(name: &str, namespace: &str, values: &[(&str, &str)]) -> Value {
+    let secret = k8s_openapi::api::core::v1::Secret {
+        metadata: k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: Some(namespace.to_string()),
+            ..Default::default()
+        },
+        data: Some(
+            values
+                .iter()
+                .map(|(key, value)| {
+                    (
+                        key.to_string(),
+                        k8s_openapi::ByteString(value.as_bytes().to_vec()),
+                    )
+                })
+                .collect(),
+        ),
+        ..Default::default()
+    };
+    let mut value = serde_json::to_value(secret).unwrap();
+    value["apiVersion"] = json!("v1");
+    value["kind"] = json!("Secret");
+    value
+}
+
+/// A ConfigMap with string values, as returned by the API server
+pub fn config_map(name: &str, namespace: &str, values: &[(&str, &str)]) -> Value {
+    let data: serde_json::Map<String, Value> = values
+        .iter()
+        .map(|(key, value)| (key.to_string(), json!(value)))
+        .collect();
+    json!({
+        "apiVersion": "v1",
+        "kind": "ConfigMap",
+        "metadata": { "name": name, "namespace": namespace },
+        "data": data,
+    })
+}
+
+/// Runs the binary in `dir`, using `dir/kubeconfig` as kubeconfig
+pub fn nais_env(dir: &TestDir, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nais-env"))
+        .args(args)
+        .current_dir(dir.path())
+        .env("KUBECONFIG", dir.path().join("kubeconfig"))
+        .env("HOME", dir.path())
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("NAIS_ENV_ACTIVE")
+        .output()
+        .expect("Failed to run nais-env")
+}
+
+/// Stderr of a finished run, as a string
+pub fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+/// Makes `dir` a git repository, so repository root discovery works
+pub fn git_init(dir: &TestDir) {
+    let status = Command::new("git")
+        .args(["init", "--quiet"])
+        .current_dir(dir.path())
+        .status()
+        .expect("Failed to run git init");
+    assert!(status.success());
+}
+
+/// A fake cluster with the workload `app` in the namespace `team`, and a test directory
+/// with a nais.yaml for it and a kubeconfig pointing at the cluster
+///
+/// # Example
+///
+/// ```ignore
+/// let (server, dir) = Fixture::new("commands")
+///     .env_from_secret("app", &[("API_KEY", "key")])
+///     .start();
+/// ```
+pub struct Fixture {
+    dir_name: String,
+    context: String,
+    workload: Workload,
+    container: Value,
+    volumes: Option<Value>,
+    resources: Vec<(String, Value)>,
+}
+
+impl Fixture {
+    /// A Deployment without environment, in a kubeconfig context named `nais-dev`
+    pub fn new(dir_name: &str) -> Self {
+        Self {
+            dir_name: dir_name.to_string(),
+            context: String::from("nais-dev"),
+            workload: Workload::Deployment,
+            container: json!({}),
+            volumes: None,
+            resources: Vec::new(),
+        }
+    }
+
+    /// Sets the name of the kubeconfig context
+    pub fn context(mut self, context: &str) -> Self {
+        self.context = context.to_string();
+        self
+    }
+
+    /// Serves the pod template in a CronJob or Job instead of a Deployment, for a Naisjob
+    pub fn workload(mut self, workload: Workload) -> Self {
+        self.workload = workload;
+        self
+    }
+
+    /// Sets fields on the container of the workload, e.g. `env` or `volumeMounts`
+    ///
+    /// A field replaces what was set before, including `envFrom` from `env_from_secret`.
+    pub fn container(mut self, fields: Value) -> Self {
+        for (key, value) in fields
+            .as_object()
+            .expect("container fields must be an object")
+        {
+            self.container[key] = value.clone();
+        }
+        self
+    }
+
+    /// Sets the volumes of the workload's pod template
+    pub fn volumes(mut self, volumes: Value) -> Self {
+        self.volumes = Some(volumes);
+        self
+    }
+
+    /// Serves a secret in `team`, without referencing it from the workload
+    pub fn secret(self, name: &str, values: &[(&str, &str)]) -> Self {
+        let path = format!("/api/v1/namespaces/team/secrets/{}", name);
+        self.resource(&path, secret(name, "team", values))
+    }
+
+    /// Serves a secret in `team` and references it with `envFrom` in the workload
+    pub fn env_from_secret(mut self, name: &str, values: &[(&str, &str)]) -> Self {
+        let env_from = self.container["envFrom"].as_array().cloned();
+        let mut env_from = env_from.unwrap_or_default();
+        env_from.push(json!({ "secretRef": { "name": name } }));
+        self.container["envFrom"] = json!(env_from);
+        self.secret(name, values)
+    }
+
+    /// Serves a config map in `team`, without referencing it from the workload
+    pub fn config_map(self, name: &str, values: &[(&str, &str)]) -> Self {
+        let path = format!("/api/v1/namespaces/team/configmaps/{}", name);
+        self.resource(&path, config_map(name, "team", values))
+    }
+
+    /// Serves any resource at an API path, e.g. another Deployment
+    pub fn resource(mut self, path: &str, body: Value) -> Self {
+        self.resources.push((path.to_string(), body));
+        self
+    }
+
+    /// Starts the fake API server and writes `nais.yaml` and `kubeconfig` to a new directory
+    pub fn start(self) -> (FakeApiServer, TestDir) {
+        let mut resources = self.resources;
+        resources.push(self.workload.resource(self.container, self.volumes));
+
+        let server = FakeApiServer::start(move |path| {
+            match resources.iter().find(|(resource, _)| resource == path) {
+                Some((_, body)) => Response::ok(body.clone()),
+                None => Response::status(404, "NotFound", "not found"),
+            }
+        });
+
+        let dir = TestDir::new(&self.dir_name);
+        dir.write("nais.yaml", &nais_yaml("app", "team"));
+        dir.write(
+            "kubeconfig",
+            &kubeconfig(&self.context, server.url(), token_user()),
+        );
+        (server, dir)
+    }
+}
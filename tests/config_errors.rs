@@ -0,0 +1,91 @@
+//! Errors in the config point at the file, line and column, the field and the template line
+
+mod common;
+
+use common::{TestDir, nais_env};
+
+const HEADER: &str = "\
+apiVersion: nais.io/v1alpha1
+kind: Application
+metadata:
+  name: app
+  namespace: team
+spec:
+  image: ghcr.io/navikt/app:1
+";
+
+#[test]
+fn plain_config_error_has_location_and_field() {
+    let dir = TestDir::new("config-errors");
+    dir.write(
+        "nais.yaml",
+        &format!(
+            "{}  accessPolicy:\n    outbound:\n      external:\n        - host: example.com\n          ports:\n            - port: http\n",
+            HEADER
+        ),
+    );
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml"]);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(12), "{}", stderr);
+    assert!(
+        stderr.contains(
+            "Invalid configuration in nais.yaml:13:21: invalid type: string \"http\", expected u16"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("\n  field: spec.accessPolicy.outbound.external[0].ports[0].port"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("template line"), "{}", stderr);
+}
+
+#[test]
+fn templated_config_error_has_location_field_and_template_line() {
+    let dir = TestDir::new("config-errors");
+    // The loop adds lines, so the line in the processed config differs from the template
+    dir.write(
+        "nais.yaml",
+        &format!(
+            "{}  ingresses:\n{{{{#each ingresses}}}}\n    - {{{{ this }}}}\n{{{{/each}}}}\n  accessPolicy:\n    outbound:\n      external:\n        - host: example.com\n          ports:\n            - port: {{{{ port }}}}\n",
+            HEADER
+        ),
+    );
+    dir.write(
+        "vars.yaml",
+        "ingresses:\n  - https://a.example.com\n  - https://b.example.com\n  - https://c.example.com\n  - https://d.example.com\nport: http\n",
+    );
+    let output = nais_env(
+        &dir,
+        &[
+            "template",
+            "--config",
+            "nais.yaml",
+            "--variables",
+            "vars.yaml",
+        ],
+    );
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(12), "{}", stderr);
+    assert!(
+        stderr.contains(
+            "Invalid configuration in nais.yaml:18:21: invalid type: string \"http\", expected u16"
+        ),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("\n  field: spec.accessPolicy.outbound.external[0].ports[0].port"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("\n  template line 17: - port: {{ port }}"),
+        "{}",
+        stderr
+    );
+}
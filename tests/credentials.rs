@@ -0,0 +1,119 @@
+//! Cluster credential and connection failures are reported with an exit code and a fix
+
+mod common;
+
+use std::net::TcpListener;
+
+use common::{FakeApiServer, Response, TestDir, exec_user, kubeconfig, nais_env, nais_yaml};
+
+/// Writes a nais.yaml and a kubeconfig for `server`, and runs `nais-env --print` on them
+fn run_against(server: &str, context: &str, user: serde_json::Value) -> std::process::Output {
+    let dir = TestDir::new("credentials");
+    dir.write("nais.yaml", &nais_yaml("app", "team"));
+    dir.write("kubeconfig", &kubeconfig(context, server, user));
+    nais_env(&dir, &["--config", "nais.yaml", "--print"])
+}
+
+#[test]
+fn missing_context_lists_available_contexts() {
+    let output = run_against("http://127.0.0.1:1", "other-cluster", common::token_user());
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(24), "{}", stderr);
+    assert!(
+        stderr.contains("context 'nais-dev' is not available"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("nais kubeconfig"), "{}", stderr);
+    assert!(stderr.contains("other-cluster"), "{}", stderr);
+}
+
+#[test]
+fn failing_exec_plugin_asks_to_log_in_again() {
+    let output = run_against("http://127.0.0.1:1", "nais-dev", exec_user("false"));
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(20), "{}", stderr);
+    assert!(
+        stderr.contains("credential plugin in your kubeconfig failed"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("nais login"), "{}", stderr);
+}
+
+#[test]
+fn missing_exec_plugin_asks_to_install_it() {
+    let output = run_against(
+        "http://127.0.0.1:1",
+        "nais-dev",
+        exec_user("nais-env-test-missing-plugin"),
+    );
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(20), "{}", stderr);
+    assert!(stderr.contains("could not be started"), "{}", stderr);
+}
+
+#[test]
+fn unauthorized_asks_to_log_in_again() {
+    let server = FakeApiServer::start(|_| Response::status(401, "Unauthorized", "Unauthorized"));
+    let output = run_against(server.url(), "nais-dev", common::token_user());
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(20), "{}", stderr);
+    assert!(stderr.contains("expired or invalid"), "{}", stderr);
+    assert!(stderr.contains("nais login"), "{}", stderr);
+    assert!(
+        server
+            .requests()
+            .iter()
+            .any(|request| request.contains("/namespaces/team/deployments/app")),
+        "{:?}",
+        server.requests()
+    );
+}
+
+#[test]
+fn forbidden_asks_to_check_team_membership() {
+    let server = FakeApiServer::start(|_| {
+        Response::status(403, "Forbidden", "deployments.apps \"app\" is forbidden")
+    });
+    let output = run_against(server.url(), "nais-dev", common::token_user());
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(20), "{}", stderr);
+    assert!(stderr.contains("is forbidden"), "{}", stderr);
+    assert!(stderr.contains("member of the team"), "{}", stderr);
+}
+
+#[test]
+fn connection_refused_asks_to_connect_naisdevice() {
+    // Bind and release a port, so nothing is listening on it
+    let address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let output = run_against(
+        &format!("http://{}", address),
+        "nais-dev",
+        common::token_user(),
+    );
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(25), "{}", stderr);
+    assert!(stderr.contains("naisdevice"), "{}", stderr);
+}
+
+#[test]
+fn missing_deployment_is_not_found() {
+    let server = FakeApiServer::start(|_| {
+        Response::status(404, "NotFound", "deployments.apps \"app\" not found")
+    });
+    let output = run_against(server.url(), "nais-dev", common::token_user());
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(21), "{}", stderr);
+    assert!(stderr.contains("not found"), "{}", stderr);
+}
@@ -0,0 +1,141 @@
+//! Config and variables files are found in .nais/ when --config is not given
+
+mod common;
+
+use common::{TestDir, git_init, nais_env, nais_yaml};
+
+/// A git repository with the given files, run with `nais-env template` to avoid the cluster
+fn run(files: &[(&str, &str)], args: &[&str]) -> std::process::Output {
+    let dir = TestDir::new("discovery");
+    git_init(&dir);
+    for (name, content) in files {
+        dir.write(name, content);
+    }
+    let mut all_args = vec!["template"];
+    all_args.extend(args);
+    nais_env(&dir, &all_args)
+}
+
+const TEMPLATE: &str = "\
+apiVersion: nais.io/v1alpha1
+kind: Application
+metadata:
+  name: app
+  namespace: team
+spec:
+  image: {{ image }}
+";
+
+#[test]
+fn single_template_and_variables_file_is_picked() {
+    let output = run(
+        &[
+            (".nais/nais.yaml", TEMPLATE),
+            (".nais/vars-dev.yaml", "image: dev-image\n"),
+        ],
+        &[],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("image: dev-image"), "{}", stdout);
+}
+
+#[test]
+fn several_environments_need_env() {
+    let files = [
+        (".nais/nais.yaml", TEMPLATE),
+        (".nais/vars-dev.yaml", "image: dev-image\n"),
+        (".nais/prod.yaml", "image: prod-image\n"),
+    ];
+
+    let output = run(&files, &[]);
+    let stderr = common::stderr(&output);
+    assert_eq!(output.status.code(), Some(17), "{}", stderr);
+    assert!(stderr.contains("use --env"), "{}", stderr);
+    assert!(stderr.contains("dev: "), "{}", stderr);
+    assert!(stderr.contains("prod: "), "{}", stderr);
+
+    let output = run(&files, &["--env", "prod"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("image: prod-image"), "{}", stdout);
+}
+
+#[test]
+fn plain_manifests_are_environments() {
+    let manifest = nais_yaml("app", "team");
+    let output = run(
+        &[
+            (".nais/dev-gcp.yaml", &manifest),
+            (".nais/prod-gcp.yaml", &manifest),
+        ],
+        &["--env", "dev-gcp"],
+    );
+    let stderr = common::stderr(&output);
+
+    // There is no template to print, so this only checks which manifest was picked
+    assert!(stderr.contains("Using dev-gcp: "), "{}", stderr);
+    assert!(stderr.contains(".nais/dev-gcp.yaml"), "{}", stderr);
+}
+
+#[test]
+fn per_app_folders_are_selected_with_app_and_env() {
+    let files = [
+        (".nais/api/nais.yaml", TEMPLATE),
+        (".nais/api/dev.yaml", "image: api-image\n"),
+        (".nais/worker/nais.yaml", TEMPLATE),
+        (".nais/worker/dev.yaml", "image: worker-image\n"),
+    ];
+
+    let output = run(&files, &["--env", "dev"]);
+    let stderr = common::stderr(&output);
+    assert_eq!(output.status.code(), Some(17), "{}", stderr);
+    assert!(stderr.contains("api/dev"), "{}", stderr);
+
+    let output = run(&files, &["--env", "worker/dev"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("image: worker-image"), "{}", stdout);
+}
+
+#[test]
+fn missing_nais_directory_asks_for_config() {
+    let output = run(&[], &[]);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(17), "{}", stderr);
+    assert!(stderr.contains("--config"), "{}", stderr);
+}
+
+#[test]
+fn other_kubernetes_resources_are_ignored() {
+    let alerts = "\
+apiVersion: monitoring.coreos.com/v1
+kind: PrometheusRule
+metadata:
+  name: app-alerts
+  namespace: team
+spec:
+  groups: []
+";
+    let topic = "\
+apiVersion: kafka.nais.io/v1
+kind: Topic
+metadata:
+  name: {{ topic }}
+";
+    let output = run(
+        &[
+            (".nais/nais.yaml", TEMPLATE),
+            (".nais/dev.yaml", "image: dev-image\n"),
+            (".nais/alerts.yaml", alerts),
+            (".nais/topic.yaml", topic),
+        ],
+        &[],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("image: dev-image"), "{}", stdout);
+}
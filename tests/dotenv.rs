@@ -0,0 +1,50 @@
+//! Override files are parsed with the dotenv grammar, and errors point at the line and column
+//!
+//! The grammar itself is covered by the unit tests in src/dotenv.rs.
+
+mod common;
+
+use common::{Fixture, nais_env};
+
+#[test]
+fn override_values_reach_the_program() {
+    let (_server, dir) = Fixture::new("dotenv").start();
+    dir.write("local.env", "export GREETING='Hello $USER' # comment\n");
+
+    let output = nais_env(
+        &dir,
+        &[
+            "run",
+            "--config",
+            "nais.yaml",
+            "--overrides",
+            "local.env",
+            "--",
+            "sh",
+            "-c",
+            "printf '%s' \"$GREETING\"",
+        ],
+    );
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello $USER");
+}
+
+#[test]
+fn errors_have_the_file_line_and_column() {
+    let (_server, dir) = Fixture::new("dotenv").start();
+    dir.write("local.env", "OK=1\nMISSING_EQUALS\n");
+
+    let output = nais_env(
+        &dir,
+        &["print", "--config", "nais.yaml", "--overrides", "local.env"],
+    );
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(18), "{}", stderr);
+    assert!(
+        stderr.contains("local.env:2:15: Expected '=' after MISSING_EQUALS"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,122 @@
+//! The output formats are written and inferred from the file extension
+//!
+//! That dotenv values round-trip through the parser and sh is covered by the unit tests in
+//! src/env_file.rs.
+
+mod common;
+
+use common::{FakeApiServer, Fixture, TestDir, nais_env};
+use std::collections::BTreeMap;
+use std::process::Command;
+
+/// Fetches `values` as a secret and writes them to `.env`
+fn fetch(values: &BTreeMap<String, String>) -> (FakeApiServer, TestDir) {
+    let pairs: Vec<(&str, &str)> = values
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    let (server, dir) = Fixture::new("env-file")
+        .env_from_secret("app", &pairs)
+        .start();
+
+    let output = nais_env(&dir, &["fetch", "--config", "nais.yaml"]);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    (server, dir)
+}
+
+/// Values with the characters each format has to handle
+fn tricky_values() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (String::from("PLAIN"), String::from("plain")),
+        (String::from("QUOTES"), String::from("it's \"quoted\"")),
+        (
+            String::from("SHELL"),
+            String::from("$HOME `date` \\n # not a comment"),
+        ),
+        (String::from("PEM"), String::from("line 1\nline 2")),
+    ])
+}
+
+#[test]
+fn format_is_inferred_from_the_file_extension() {
+    let values = tricky_values();
+    let (_server, dir) = fetch(&values);
+
+    for (file, check) in [("env.json", "json"), ("env.yaml", "yaml"), ("env.sh", "sh")] {
+        let output = nais_env(&dir, &["fetch", "--config", "nais.yaml", "--file", file]);
+        assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+
+        let content = std::fs::read_to_string(dir.path().join(file)).unwrap();
+        let parsed: BTreeMap<String, String> = match check {
+            "json" => serde_json::from_str(&content).unwrap(),
+            "yaml" => serde_yaml::from_str(&content).unwrap(),
+            _ => {
+                let output = Command::new("sh")
+                    .args(["-c", &format!(". ./{}; env -0", file)])
+                    .current_dir(dir.path())
+                    .env_clear()
+                    .output()
+                    .unwrap();
+                String::from_utf8_lossy(&output.stdout)
+                    .split('\0')
+                    .filter_map(|entry| entry.split_once('='))
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect()
+            }
+        };
+
+        for (key, value) in &values {
+            assert_eq!(parsed.get(key), Some(value), "{}:\n{}", file, content);
+        }
+    }
+}
+
+#[test]
+fn print_writes_the_requested_format() {
+    let values = tricky_values();
+    let (_server, dir) = fetch(&values);
+    let print = |format: &str| {
+        let output = nais_env(
+            &dir,
+            &["print", "--config", "nais.yaml", "--format", format],
+        );
+        assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+        (
+            String::from_utf8_lossy(&output.stdout).to_string(),
+            common::stderr(&output),
+        )
+    };
+
+    let (stdout, _) = print("fish");
+    assert!(
+        stdout.contains("set -gx QUOTES 'it\\'s \"quoted\"'"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("set -gx SHELL '$HOME `date` \\\\n # not a comment'"),
+        "{}",
+        stdout
+    );
+
+    let (stdout, _) = print("powershell");
+    assert!(
+        stdout.contains("$env:QUOTES = 'it''s \"quoted\"'"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("$env:PEM = 'line 1\nline 2'"), "{}", stdout);
+
+    let (stdout, stderr) = print("docker");
+    assert!(stdout.contains("QUOTES=it's \"quoted\"\n"), "{}", stdout);
+    assert!(!stdout.contains("PEM="), "{}", stdout);
+    assert!(
+        stderr.contains("Skipping PEM, docker env files can not contain newlines"),
+        "{}",
+        stderr
+    );
+
+    let (stdout, _) = print("dotenv");
+    assert!(stdout.contains("PLAIN=plain\n"), "{}", stdout);
+    assert!(stdout.contains("PEM='line 1\nline 2'"), "{}", stdout);
+}
@@ -0,0 +1,127 @@
+//! Variables and files are read from the pod template of the workload in the cluster
+
+mod common;
+
+use common::{FakeApiServer, Fixture, TestDir, nais_env};
+use serde_json::json;
+
+/// A Deployment referencing the secret `app`, which exists, and `gone`, which does not
+fn setup(required_missing: bool) -> (FakeApiServer, TestDir) {
+    Fixture::new("env-from")
+        .env_from_secret("app", &[("API_KEY", "key")])
+        .container(json!({
+            "envFrom": [
+                { "secretRef": { "name": "app" } },
+                { "secretRef": { "name": "gone", "optional": true } },
+                { "configMapRef": { "name": "gone", "optional": true } },
+                { "secretRef": { "name": "gone", "optional": !required_missing } },
+            ],
+            "env": [
+                {
+                    "name": "REQUIRED_KEY",
+                    "valueFrom": { "secretKeyRef": { "name": "app", "key": "API_KEY" } },
+                },
+                {
+                    "name": "OPTIONAL_KEY",
+                    "valueFrom": {
+                        "secretKeyRef": { "name": "app", "key": "NOPE", "optional": true },
+                    },
+                },
+                {
+                    "name": "OPTIONAL_SECRET",
+                    "valueFrom": {
+                        "secretKeyRef": { "name": "gone", "key": "x", "optional": true },
+                    },
+                },
+            ],
+            "volumeMounts": [{ "name": "certs", "mountPath": "/var/run/secrets/certs" }],
+        }))
+        .volumes(json!([{ "name": "certs", "secret": { "secretName": "app" } }]))
+        .start()
+}
+
+#[test]
+fn optional_sources_and_keys_are_skipped_silently() {
+    let (_server, dir) = setup(false);
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stdout.contains("API_KEY=key\n"), "{}", stdout);
+    assert!(stdout.contains("REQUIRED_KEY=key\n"), "{}", stdout);
+    assert!(!stdout.contains("OPTIONAL_"), "{}", stdout);
+    assert!(!stderr.contains("Failed to fetch"), "{}", stderr);
+}
+
+#[test]
+fn missing_sources_that_are_not_optional_are_reported() {
+    let (_server, dir) = setup(true);
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml"]);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(23), "{}", stderr);
+    assert_eq!(stderr.matches("secret gone").count(), 1, "{}", stderr);
+    assert!(
+        stderr.contains("Warning: Failed to fetch secret gone"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Error: The environment is incomplete, 1 source could not be fetched"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("OPTIONAL_"), "{}", stderr);
+}
+
+#[test]
+fn workload_is_fetched_once() {
+    let (server, dir) = setup(false);
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml", "--files=files"]);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(
+        dir.path()
+            .join("files/var/run/secrets/certs/API_KEY")
+            .exists()
+    );
+
+    let workload_requests = server
+        .requests()
+        .iter()
+        .filter(|request| request.contains("/deployments/"))
+        .count();
+    assert_eq!(workload_requests, 1, "{:?}", server.requests());
+}
+
+#[test]
+fn config_maps_are_applied_in_order_with_secrets() {
+    let (_server, dir) = Fixture::new("env-from-configmap")
+        .config_map(
+            "config",
+            &[("URL", "http://app"), ("API_KEY", "from-config")],
+        )
+        .secret("app", &[("API_KEY", "key")])
+        .container(json!({
+            "envFrom": [
+                { "configMapRef": { "name": "config" } },
+                { "configMapRef": { "name": "config" }, "prefix": "CFG_" },
+                { "secretRef": { "name": "app" } },
+            ],
+        }))
+        .start();
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("URL=http://app\n"), "{}", stdout);
+    assert!(stdout.contains("CFG_URL=http://app\n"), "{}", stdout);
+    assert!(stdout.contains("CFG_API_KEY=from-config\n"), "{}", stdout);
+    assert!(stdout.contains("API_KEY=key\n"), "{}", stdout);
+    assert!(
+        !stdout.lines().any(|line| line == "API_KEY=from-config"),
+        "{}",
+        stdout
+    );
+}
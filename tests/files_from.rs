@@ -0,0 +1,184 @@
+//! Files from `filesFrom` are written below the `--files` directory
+
+mod common;
+
+use common::{FakeApiServer, Fixture, TestDir, nais_env};
+use serde_json::json;
+
+/// A nais.yaml mounting the secret `certs` and the secret `missing`, which does not exist
+fn setup() -> (FakeApiServer, TestDir) {
+    setup_with_files_from(json!([
+        { "secret": "certs", "mountPath": "/var/run/secrets/certs" },
+        { "secret": "missing", "mountPath": "/var/run/secrets/missing" },
+    ]))
+}
+
+fn setup_with_files_from(files_from: serde_json::Value) -> (FakeApiServer, TestDir) {
+    let (server, dir) = Fixture::new("files-from")
+        .secret("certs", &[("ca.pem", "certificate")])
+        .start();
+    dir.write(
+        "nais.yaml",
+        &json!({
+            "apiVersion": "nais.io/v1alpha1",
+            "kind": "Application",
+            "metadata": { "name": "app", "namespace": "team", "labels": { "team": "team" } },
+            "spec": {
+                "image": "ghcr.io/navikt/app:1",
+                "filesFrom": files_from,
+            },
+        })
+        .to_string(),
+    );
+    (server, dir)
+}
+
+#[test]
+fn files_are_written_even_if_some_are_missing() {
+    let (_server, dir) = setup();
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml", "--files=files"]);
+
+    assert_eq!(
+        output.status.code(),
+        Some(23),
+        "{}",
+        common::stderr(&output)
+    );
+    let content =
+        std::fs::read_to_string(dir.path().join("files/var/run/secrets/certs/ca.pem")).unwrap();
+    assert_eq!(content, "certificate");
+}
+
+#[test]
+fn strict_does_not_write_any_files_when_some_are_missing() {
+    let (_server, dir) = setup();
+    let output = nais_env(
+        &dir,
+        &[
+            "print",
+            "--config",
+            "nais.yaml",
+            "--files=files",
+            "--strict",
+        ],
+    );
+
+    assert_eq!(
+        output.status.code(),
+        Some(23),
+        "{}",
+        common::stderr(&output)
+    );
+    assert!(
+        common::stderr(&output).contains("secret missing"),
+        "{}",
+        common::stderr(&output)
+    );
+    assert!(!dir.path().join("files").exists());
+}
+
+/// A git repository with a nais.yaml mounting the secret `certs`
+fn setup_repo() -> (FakeApiServer, TestDir) {
+    let (server, dir) = setup_with_files_from(json!([
+        { "secret": "certs", "mountPath": "/var/run/secrets/certs" },
+    ]));
+    common::git_init(&dir);
+    (server, dir)
+}
+
+#[test]
+fn clear_removes_the_files_directory() {
+    let (_server, dir) = setup_repo();
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml", "--files"]);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(dir.path().join(".nais-env/files").is_dir());
+
+    let output = nais_env(&dir, &["clear"]);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(!dir.path().join(".nais-env/files").exists());
+}
+
+#[test]
+fn clear_does_not_remove_a_files_directory_that_already_existed() {
+    let (_server, dir) = setup_repo();
+    dir.write("src/main.rs", "fn main() {}");
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml", "--files=src"]);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(
+        common::stderr(&output).contains("is not removed by nais-env clear"),
+        "{}",
+        common::stderr(&output)
+    );
+
+    let output = nais_env(&dir, &["clear"]);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(dir.path().join("src/main.rs").exists());
+}
+
+#[test]
+fn clear_does_not_remove_anything_for_a_files_directory_outside_the_repository() {
+    let (_server, dir) = setup_repo();
+    let outside = TestDir::new("files-from-outside");
+    let outside_files = outside.path().join("files");
+    dir.write("files/keep.txt", "keep");
+
+    let output = nais_env(
+        &dir,
+        &[
+            "print",
+            "--config",
+            "nais.yaml",
+            &format!("--files={}", outside_files.display()),
+        ],
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(outside_files.join("var/run/secrets/certs/ca.pem").exists());
+
+    let output = nais_env(&dir, &["clear"]);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(outside_files.is_dir());
+    assert!(dir.path().join("files/keep.txt").exists());
+}
+
+#[test]
+fn files_without_a_directory_does_not_take_the_next_argument() {
+    let (_server, dir) = setup_repo();
+    dir.write(".env", "API_KEY=old\n");
+
+    let output = nais_env(&dir, &["--config", "nais.yaml", "--files", "print"]);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(
+        dir.path()
+            .join(".nais-env/files/var/run/secrets/certs/ca.pem")
+            .exists()
+    );
+
+    let output = nais_env(&dir, &["diff", "--config", "nais.yaml", "--files", ".env"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("- API_KEY"), "{}", stdout);
+    assert!(!dir.path().join(".env/var").exists());
+}
+
+#[test]
+fn mount_paths_outside_the_files_directory_are_skipped() {
+    let (_server, dir) = setup_with_files_from(json!([
+        { "secret": "certs", "mountPath": "/../escaped" },
+        { "secret": "certs", "mountPath": "/var/run/secrets/certs" },
+    ]));
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml", "--files=files"]);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(
+        stderr.contains("Skipping /../escaped from secret certs"),
+        "{}",
+        stderr
+    );
+    assert!(!dir.path().join("escaped").exists());
+    assert!(
+        dir.path()
+            .join("files/var/run/secrets/certs/ca.pem")
+            .exists()
+    );
+}
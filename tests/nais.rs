@@ -0,0 +1,243 @@
+//! A config file may bundle the workload with other resources, or contain several workloads,
+//! and a Naisjob reads its pod template from a CronJob or a Job
+
+mod common;
+
+use common::{FakeApiServer, Fixture, TestDir, Workload, deployment, nais_env, nais_yaml};
+use serde_json::json;
+
+const TOPIC: &str = "\
+apiVersion: kafka.nais.io/v1
+kind: Topic
+metadata:
+  name: events
+  namespace: team
+";
+
+/// The Deployments `app` and `worker`, each with its own secret, and the documents written
+/// to `nais.yaml`, separated by `---`
+fn setup(documents: &[&str]) -> (FakeApiServer, TestDir) {
+    let worker = deployment(
+        "worker",
+        "team",
+        json!({ "envFrom": [{ "secretRef": { "name": "worker" } }] }),
+    );
+    let (server, dir) = Fixture::new("nais")
+        .env_from_secret("app", &[("APP_KEY", "app")])
+        .secret("worker", &[("WORKER_KEY", "worker")])
+        .resource("/apis/apps/v1/namespaces/team/deployments/worker", worker)
+        .start();
+    dir.write("nais.yaml", &documents.join("\n---\n"));
+    (server, dir)
+}
+
+#[test]
+fn workload_is_picked_from_several_documents() {
+    let app = nais_yaml("app", "team");
+    let (_server, dir) = setup(&[TOPIC, &app]);
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stdout.contains("APP_KEY=app"), "{}", stdout);
+    assert!(
+        stderr.contains("Skipping document 1 (kafka.nais.io/v1 Topic events)"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn several_workloads_need_select() {
+    let app = nais_yaml("app", "team");
+    let worker = nais_yaml("worker", "team");
+    let (server, dir) = setup(&[&app, TOPIC, &worker]);
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml"]);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(13), "{}", stderr);
+    assert!(
+        stderr.contains("Found several workloads (app, worker), use --select to choose one"),
+        "{}",
+        stderr
+    );
+    assert!(server.requests().is_empty(), "{:?}", server.requests());
+}
+
+#[test]
+fn select_picks_the_workload_by_name() {
+    let app = nais_yaml("app", "team");
+    let worker = nais_yaml("worker", "team");
+    let (server, dir) = setup(&[&app, TOPIC, &worker]);
+    let output = nais_env(
+        &dir,
+        &["print", "--config", "nais.yaml", "--select", "worker"],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stdout.contains("WORKER_KEY=worker"), "{}", stdout);
+    assert!(!stdout.contains("APP_KEY"), "{}", stdout);
+    assert!(
+        stderr.contains("Skipping document 1 (Application app)"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("Skipping document 2 (kafka.nais.io/v1 Topic events)"),
+        "{}",
+        stderr
+    );
+    assert!(
+        !server
+            .requests()
+            .iter()
+            .any(|request| request.contains("deployments/app")),
+        "{:?}",
+        server.requests()
+    );
+}
+
+#[test]
+fn select_with_an_unknown_name_is_an_error() {
+    let app = nais_yaml("app", "team");
+    let worker = nais_yaml("worker", "team");
+    let (_server, dir) = setup(&[&app, &worker]);
+    let output = nais_env(
+        &dir,
+        &["print", "--config", "nais.yaml", "--select", "missing"],
+    );
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(13), "{}", stderr);
+    assert!(
+        stderr.contains("No Application or Naisjob named 'missing'"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn config_without_a_workload_is_an_error() {
+    let (_server, dir) = setup(&[TOPIC]);
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml"]);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(13), "{}", stderr);
+    assert!(
+        stderr.contains("Expected a document with kind: Application or kind: Naisjob"),
+        "{}",
+        stderr
+    );
+}
+
+/// A Naisjob `app` in `team`, with a schedule when one is given
+fn naisjob_yaml(schedule: Option<&str>) -> String {
+    let mut spec = json!({ "image": "ghcr.io/navikt/app:1" });
+    if let Some(schedule) = schedule {
+        spec["schedule"] = json!(schedule);
+    }
+    json!({
+        "apiVersion": "nais.io/v1",
+        "kind": "Naisjob",
+        "metadata": { "name": "app", "namespace": "team", "labels": { "team": "team" } },
+        "spec": spec,
+    })
+    .to_string()
+}
+
+/// A cluster serving the pod template of `app` in the given workload, and a Naisjob for it
+fn setup_naisjob(workload: Workload, schedule: Option<&str>) -> (FakeApiServer, TestDir) {
+    let (server, dir) = Fixture::new("naisjob")
+        .workload(workload)
+        .env_from_secret("app", &[("JOB_KEY", "job")])
+        .start();
+    dir.write("nais.yaml", &naisjob_yaml(schedule));
+    (server, dir)
+}
+
+#[test]
+fn naisjob_with_a_schedule_reads_env_from_the_cronjob() {
+    let (server, dir) = setup_naisjob(Workload::CronJob, Some("0 * * * *"));
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("JOB_KEY=job\n"), "{}", stdout);
+    assert!(
+        server.requests().contains(&String::from(
+            "GET /apis/batch/v1/namespaces/team/cronjobs/app HTTP/1.1"
+        )),
+        "{:?}",
+        server.requests()
+    );
+}
+
+#[test]
+fn naisjob_without_a_schedule_reads_env_from_the_job() {
+    let (server, dir) = setup_naisjob(Workload::Job, None);
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("JOB_KEY=job\n"), "{}", stdout);
+    assert!(
+        server.requests().contains(&String::from(
+            "GET /apis/batch/v1/namespaces/team/jobs/app HTTP/1.1"
+        )),
+        "{:?}",
+        server.requests()
+    );
+}
+
+#[test]
+fn naisjob_with_a_schedule_does_not_fall_back_to_a_job() {
+    let (server, dir) = setup_naisjob(Workload::Job, Some("0 * * * *"));
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml"]);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(21), "{}", stderr);
+    assert!(stderr.contains("cronjob 'app'"), "{}", stderr);
+    assert!(
+        !server
+            .requests()
+            .iter()
+            .any(|request| request.contains("/jobs/")),
+        "{:?}",
+        server.requests()
+    );
+}
+
+#[test]
+fn naiserator_variables_are_injected_and_overridden_by_env() {
+    let (_server, dir) = Fixture::new("injected").start();
+    dir.write(
+        "nais.yaml",
+        &json!({
+            "apiVersion": "nais.io/v1alpha1",
+            "kind": "Application",
+            "metadata": { "name": "app", "namespace": "team", "labels": { "team": "team" } },
+            "spec": {
+                "image": "ghcr.io/navikt/app:1",
+                "env": [{ "name": "NAIS_APP_IMAGE", "value": "app:local" }],
+            },
+        })
+        .to_string(),
+    );
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    for expected in [
+        "NAIS_APP_NAME=app\n",
+        "NAIS_NAMESPACE=team\n",
+        "NAIS_CLUSTER_NAME=dev-gcp\n",
+        "NAIS_CLIENT_ID=dev-gcp:team:app\n",
+        "LOG4J_FORMAT_MSG_NO_LOOKUPS=true\n",
+        "NAIS_APP_IMAGE=app:local\n",
+    ] {
+        assert!(stdout.contains(expected), "{}", stdout);
+    }
+}
@@ -0,0 +1,157 @@
+//! The policy file decides which contexts, servers and secrets nais-env may use
+
+mod common;
+
+use common::{FakeApiServer, Fixture, TestDir, nais_env};
+
+/// A Deployment with envFrom on two secrets, `app` and `app-db`, and a user policy
+fn setup(context: &str, policy: &str) -> (FakeApiServer, TestDir) {
+    let (server, dir) = fixture(context);
+    dir.write(".config/nais-env/policy.yaml", policy);
+    (server, dir)
+}
+
+fn fixture(context: &str) -> (FakeApiServer, TestDir) {
+    Fixture::new("policy")
+        .context(context)
+        .env_from_secret("app", &[("API_KEY", "key")])
+        .env_from_secret("app-db", &[("DB_PASSWORD", "password")])
+        .start()
+}
+
+#[test]
+fn context_outside_the_policy_is_rejected() {
+    let (_server, dir) = setup("nais-dev", "contexts:\n  dev-gcp: {}\n");
+    let output = nais_env(&dir, &["--config", "nais.yaml", "--print"]);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(26), "{}", stderr);
+    assert!(
+        stderr.contains("Context 'nais-dev' is not allowed"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("dev-gcp"), "{}", stderr);
+}
+
+#[test]
+fn context_from_the_policy_is_allowed() {
+    let (_server, dir) = setup("test-tenant", "contexts:\n  test-tenant: {}\n");
+    let output = nais_env(
+        &dir,
+        &[
+            "--config",
+            "nais.yaml",
+            "--print",
+            "--context",
+            "test-tenant",
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("API_KEY=key"), "{}", stdout);
+}
+
+#[test]
+fn context_pointing_at_another_server_is_rejected() {
+    let policy = "contexts:\n  nais-dev:\n    servers:\n      - https://dev.example.com\n";
+    let (server, dir) = setup("nais-dev", policy);
+    let output = nais_env(&dir, &["--config", "nais.yaml", "--print"]);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(26), "{}", stderr);
+    assert!(stderr.contains("https://dev.example.com"), "{}", stderr);
+    assert!(server.requests().is_empty(), "{:?}", server.requests());
+}
+
+#[test]
+fn context_pointing_at_an_allowed_server_is_used() {
+    let (server, dir) = fixture("nais-dev");
+    let policy = format!(
+        "contexts:\n  nais-dev:\n    servers:\n      - {}/\n",
+        server.url()
+    );
+    dir.write(".config/nais-env/policy.yaml", &policy);
+    let output = nais_env(&dir, &["--config", "nais.yaml", "--print"]);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+}
+
+#[test]
+fn denied_secrets_are_never_fetched() {
+    let policy = "contexts:\n  nais-dev:\n    deniedSecrets:\n      - '*-db'\n";
+    let (server, dir) = setup("nais-dev", policy);
+    let output = nais_env(&dir, &["--config", "nais.yaml", "--print"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stdout.contains("API_KEY=key"), "{}", stdout);
+    assert!(!stdout.contains("DB_PASSWORD"), "{}", stdout);
+    assert!(stderr.contains("Secret 'app-db' is denied"), "{}", stderr);
+    assert!(
+        !server
+            .requests()
+            .iter()
+            .any(|request| request.contains("secrets/app-db")),
+        "{:?}",
+        server.requests()
+    );
+}
+
+/// A git repository with a repository policy and no user policy
+fn setup_repo_policy(context: &str, policy: &str) -> (FakeApiServer, TestDir) {
+    let (server, dir) = fixture(context);
+    common::git_init(&dir);
+    dir.write(".nais-env-policy.yaml", policy);
+    (server, dir)
+}
+
+#[test]
+fn repository_policy_can_not_allow_more_contexts() {
+    let (server, dir) =
+        setup_repo_policy("prod-gcp", "contexts:\n  prod-gcp: {}\n  nais-dev: {}\n");
+    let output = nais_env(
+        &dir,
+        &["print", "--config", "nais.yaml", "--context", "prod-gcp"],
+    );
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(26), "{}", stderr);
+    assert!(
+        stderr.contains("Context 'prod-gcp' is not allowed"),
+        "{}",
+        stderr
+    );
+    assert!(server.requests().is_empty(), "{:?}", server.requests());
+}
+
+#[test]
+fn repository_policy_narrows_the_user_policy() {
+    let (_server, dir) = setup_repo_policy(
+        "nais-dev",
+        "contexts:\n  nais-dev:\n    deniedSecrets:\n      - '*-db'\n",
+    );
+    dir.write(
+        ".config/nais-env/policy.yaml",
+        "contexts:\n  nais-dev: {}\n  dev-gcp: {}\n",
+    );
+
+    let output = nais_env(&dir, &["print", "--config", "nais.yaml"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("API_KEY=key"), "{}", stdout);
+    assert!(!stdout.contains("DB_PASSWORD"), "{}", stdout);
+
+    let output = nais_env(
+        &dir,
+        &["print", "--config", "nais.yaml", "--context", "dev-gcp"],
+    );
+    assert_eq!(
+        output.status.code(),
+        Some(26),
+        "{}",
+        common::stderr(&output)
+    );
+}
@@ -0,0 +1,112 @@
+//! Profiles in .nais-env.yaml provide defaults for the command line arguments
+
+mod common;
+
+use common::{FakeApiServer, Fixture, TestDir, nais_env, nais_yaml};
+
+/// A project with the nais.yaml in `.nais/`, and a kubeconfig with `context` pointing at the
+/// fake cluster
+fn setup(context: &str, project: &str) -> (FakeApiServer, TestDir) {
+    let (server, dir) = Fixture::new("profiles")
+        .context(context)
+        .env_from_secret("app", &[("API_KEY", "key")])
+        .start();
+    dir.write(".nais/nais.yaml", &nais_yaml("app", "team"));
+    dir.write("local.env", "LOCAL=yes\n");
+    dir.write(".nais-env.yaml", project);
+    dir.write(
+        ".config/nais-env/policy.yaml",
+        "contexts:\n  nais-dev: {}\n  dev-fss: {}\n",
+    );
+    (server, dir)
+}
+
+const PROJECT: &str = "\
+default: dev
+profiles:
+  dev:
+    config: .nais/nais.yaml
+    context: dev-fss
+    file: .env
+    overrides:
+      - local.env
+  other:
+    config: .nais/missing.yaml
+";
+
+#[test]
+fn default_profile_is_used_without_arguments() {
+    let (_server, dir) = setup("dev-fss", PROJECT);
+    let output = nais_env(&dir, &[]);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    let env = std::fs::read_to_string(dir.path().join(".env")).unwrap();
+    assert!(env.contains("API_KEY"), "{}", env);
+    assert!(env.contains("LOCAL"), "{}", env);
+}
+
+#[test]
+fn command_line_arguments_win_over_the_profile() {
+    let (_server, dir) = setup("nais-dev", PROJECT);
+    let output = nais_env(&dir, &["--context", "nais-dev", "--file", "cli.env"]);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(dir.path().join("cli.env").exists());
+    assert!(!dir.path().join(".env").exists());
+}
+
+#[test]
+fn named_profile_is_selected_with_profile() {
+    let (_server, dir) = setup("dev-fss", PROJECT);
+    let output = nais_env(&dir, &["--profile", "other", "print"]);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(10), "{}", stderr);
+    assert!(stderr.contains("missing.yaml"), "{}", stderr);
+}
+
+#[test]
+fn unknown_profile_lists_the_available_ones() {
+    let (_server, dir) = setup("dev-fss", PROJECT);
+    let output = nais_env(&dir, &["--profile", "prod"]);
+    let stderr = common::stderr(&output);
+
+    assert_eq!(output.status.code(), Some(16), "{}", stderr);
+    assert!(stderr.contains("Profile 'prod' not found"), "{}", stderr);
+    assert!(stderr.contains("dev, other"), "{}", stderr);
+}
+
+#[test]
+fn keep_env_works_with_clean_env_from_the_profile() {
+    let project = "\
+default: clean
+profiles:
+  clean:
+    config: .nais/nais.yaml
+    context: dev-fss
+    cleanEnv: true
+";
+    let (_server, dir) = setup("dev-fss", project);
+    let script = "printf '%s|%s|%s' \"$API_KEY\" \"${CARGO_MANIFEST_DIR-unset}\" \"${CARGO_PKG_NAME-unset}\"";
+    let output = nais_env(
+        &dir,
+        &[
+            "run",
+            "--keep-env",
+            "CARGO_MANIFEST_DIR",
+            "--",
+            "sh",
+            "-c",
+            script,
+        ],
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert_eq!(
+        stdout,
+        format!("key|{}|unset", env!("CARGO_MANIFEST_DIR")),
+        "{}",
+        common::stderr(&output)
+    );
+}
@@ -0,0 +1,274 @@
+//! The interactive shell returns its exit status and gets a prompt for bash, zsh and fish
+
+mod common;
+
+use common::{FakeApiServer, Fixture, TestDir};
+use serde_json::json;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn setup() -> (FakeApiServer, TestDir) {
+    Fixture::new("shell")
+        .env_from_secret("app", &[("API_KEY", "key")])
+        .start()
+}
+
+/// Writes an executable script that stands in for a shell
+fn fake_shell(dir: &TestDir, name: &str, script: &str) -> String {
+    use std::os::unix::fs::PermissionsExt;
+
+    dir.write(name, &format!("#!/bin/sh\n{}", script));
+    let path = dir.path().join(name);
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+/// Runs `nais-env shell` with the given `$SHELL`, writing `input` to the shell
+fn run_shell(dir: &TestDir, shell: &str, input: &str) -> Output {
+    run_shell_with_args(dir, shell, &[], input)
+}
+
+/// Runs `nais-env shell` with extra arguments, see `run_shell`
+fn run_shell_with_args(dir: &TestDir, shell: &str, args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nais-env"))
+        .args(["shell", "--config", "nais.yaml"])
+        .args(args)
+        .current_dir(dir.path())
+        .env("KUBECONFIG", dir.path().join("kubeconfig"))
+        .env("HOME", dir.path())
+        .env("SHELL", shell)
+        .env_remove("XDG_CONFIG_HOME")
+        .env_remove("NAIS_ENV_ACTIVE")
+        .env_remove("ZDOTDIR")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run nais-env");
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn shell_exits_with_the_exit_code_of_the_shell() {
+    let (_server, dir) = setup();
+    let output = run_shell(
+        &dir,
+        "/bin/sh",
+        "test \"$API_KEY\" = key || exit 1\nexit 7\n",
+    );
+
+    assert_eq!(output.status.code(), Some(7), "{}", common::stderr(&output));
+}
+
+#[test]
+fn bash_gets_a_prompt_in_ps1() {
+    let (_server, dir) = setup();
+    let bash = fake_shell(&dir, "bash", "echo \"$PS1\"\n");
+    let output = run_shell(&dir, &bash, "");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.contains("[NAIS-ENV:$NAIS_ENV_CONFIG]"), "{}", stdout);
+}
+
+#[test]
+fn zsh_gets_a_zdotdir_shim_that_sources_the_users_files() {
+    let (_server, dir) = setup();
+    let zsh = fake_shell(
+        &dir,
+        "zsh",
+        "echo \"user=$NAIS_ENV_USER_ZDOTDIR\"\ncat \"$ZDOTDIR/.zshrc\"\nls -ld \"$ZDOTDIR\"\necho \"$ZDOTDIR\" > zdotdir\nexit 4\n",
+    );
+    let output = run_shell(&dir, &zsh, "");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(4), "{}", common::stderr(&output));
+    assert!(
+        stdout.contains(&format!("user={}", dir.path().display())),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("source \"$ZDOTDIR/.zshrc\""), "{}", stdout);
+    assert!(
+        stdout.contains("PROMPT=\"%F{green}[NAIS-ENV:$NAIS_ENV_CONFIG]%f $PROMPT\""),
+        "{}",
+        stdout
+    );
+
+    // The shim is only accessible to the user
+    assert!(stdout.contains("drwx------"), "{}", stdout);
+
+    // The shim is removed when the shell exits
+    let shim = std::fs::read_to_string(dir.path().join("zdotdir")).unwrap();
+    assert!(!std::path::Path::new(shim.trim()).exists(), "{}", shim);
+}
+
+#[test]
+fn fish_gets_a_wrapped_fish_prompt() {
+    let (_server, dir) = setup();
+    let fish = fake_shell(&dir, "fish", "printf '%s\\n' \"$@\"\n");
+    let output = run_shell(&dir, &fish, "");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(stdout.starts_with("-C\n"), "{}", stdout);
+    assert!(
+        stdout.contains("functions --copy fish_prompt __nais_env_original_fish_prompt"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn shell_exports_metadata_about_the_environment() {
+    let (_server, dir) = setup();
+    let output = run_shell(
+        &dir,
+        "/bin/sh",
+        "echo \"$NAIS_ENV_CONTEXT|$NAIS_ENV_NAMESPACE|$NAIS_ENV_APP|$NAIS_ENV_SECRETS\"\necho \"$NAIS_ENV_FETCHED_AT\"\n",
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert_eq!(lines[0], "nais-dev|team|app|app", "{}", stdout);
+    assert!(
+        lines[1].len() == 20 && lines[1].ends_with('Z'),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn shell_lists_secrets_from_env_from_key_references_and_files() {
+    let (_server, dir) = Fixture::new("shell-secrets")
+        .env_from_secret("app", &[("API_KEY", "key")])
+        .secret("db", &[("password", "hunter2")])
+        .secret("certs", &[("ca.pem", "certificate")])
+        .container(json!({
+            "env": [{
+                "name": "DB_PASSWORD",
+                "valueFrom": { "secretKeyRef": { "name": "db", "key": "password" } },
+            }],
+            "volumeMounts": [{ "name": "certs", "mountPath": "/var/run/secrets/certs" }],
+        }))
+        .volumes(json!([{ "name": "certs", "secret": { "secretName": "certs" } }]))
+        .start();
+    let output = run_shell_with_args(
+        &dir,
+        "/bin/sh",
+        &["--files=files"],
+        "echo \"$NAIS_ENV_SECRETS\"\n",
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert_eq!(stdout.lines().last(), Some("app,db,certs"), "{}", stdout);
+}
+
+#[test]
+fn prompt_prints_nothing_outside_a_nais_env_shell() {
+    let dir = TestDir::new("shell");
+    let output = common::nais_env(&dir, &["prompt"]);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert!(output.stdout.is_empty(), "{:?}", output.stdout);
+}
+
+#[test]
+fn prompt_prints_the_app_and_context_inside_a_nais_env_shell() {
+    let (_server, dir) = setup();
+    let output = run_shell(
+        &dir,
+        "/bin/sh",
+        &format!("{} prompt\n", env!("CARGO_BIN_EXE_nais-env")),
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert_eq!(stdout.trim(), "team/app@nais-dev");
+}
+
+/// Starts a nested `nais-env shell` from inside a nais-env shell, after pretending the outer
+/// environment also injected `OLD_KEY`, and returns what the nested shell sees
+fn run_nested_shell(dir: &TestDir, args: &str) -> Output {
+    let report = fake_shell(
+        dir,
+        "report",
+        "echo \"$NAIS_ENV_DEPTH|${OLD_KEY-unset}|$API_KEY\"\necho \"$NAIS_ENV_KEYS\"\nexec \"$NAIS_ENV_BIN\" prompt\n",
+    );
+    let bin = env!("CARGO_BIN_EXE_nais-env");
+    run_shell(
+        dir,
+        "/bin/sh",
+        &format!(
+            "export OLD_KEY=old NAIS_ENV_KEYS=\"$NAIS_ENV_KEYS,OLD_KEY\" NAIS_ENV_BIN={bin}\nSHELL={report} {bin} shell --config nais.yaml {args}\n"
+        ),
+    )
+}
+
+#[test]
+fn nested_shell_replaces_the_environment_of_the_current_shell() {
+    let (_server, dir) = setup();
+    let output = run_nested_shell(&dir, "");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert_eq!(lines[0], "1|unset|key", "{}", stdout);
+    assert!(
+        lines[1].split(',').any(|key| key == "API_KEY"),
+        "{}",
+        stdout
+    );
+    assert!(
+        !lines[1].split(',').any(|key| key == "OLD_KEY"),
+        "{}",
+        stdout
+    );
+    assert_eq!(lines[2], "team/app@nais-dev", "{}", stdout);
+    assert!(common::stderr(&output).contains("Replacing nais.yaml with nais.yaml"));
+}
+
+#[test]
+fn nested_shell_with_stack_keeps_the_environment_and_counts_the_depth() {
+    let (_server, dir) = setup();
+    let output = run_nested_shell(&dir, "--stack");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    assert_eq!(output.status.code(), Some(0), "{}", common::stderr(&output));
+    assert_eq!(lines[0], "2|old|key", "{}", stdout);
+    assert!(
+        lines[1].split(',').any(|key| key == "OLD_KEY"),
+        "{}",
+        stdout
+    );
+    assert_eq!(lines[2], "team/app@nais-dev [2]", "{}", stdout);
+}
+
+#[test]
+fn shell_from_the_profile_is_started_instead_of_shell() {
+    let (_server, dir) = setup();
+    let zsh = fake_shell(&dir, "zsh", "echo \"zdotdir=${ZDOTDIR:+set}\"\nexit 5\n");
+    dir.write(
+        ".nais-env.yaml",
+        &format!(
+            "default: dev\nprofiles:\n  dev:\n    config: nais.yaml\n    shell: {}\n",
+            zsh
+        ),
+    );
+    let output = run_shell(&dir, "/bin/sh", "exit 1\n");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // The profile's zsh gets the same prompt setup as a zsh from $SHELL
+    assert_eq!(output.status.code(), Some(5), "{}", common::stderr(&output));
+    assert!(stdout.contains("zdotdir=set"), "{}", stdout);
+}